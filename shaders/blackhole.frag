@@ -11,41 +11,85 @@ const float R_S = 1.0;
 const float D_LAMBDA = 0.1;  // smaller step for normalized units
 
 uniform vec2 u_resolution;
+uniform vec2 u_tile_offset;  // pixel offset of the drawn tile within u_resolution
 uniform float u_time;
 uniform vec3 u_camera_pos;
 uniform mat3 u_view_matrix;
 uniform float u_fov;
 uniform bool u_render_disk;
 uniform bool u_gravitational_lensing;
+uniform float u_spin;          // dimensionless Kerr spin a/M; 0 -> Schwarzschild
 
 // Texture uniforms for Ross Ning–style visuals
 // textures
 uniform sampler2D colorMap;
 uniform samplerCube skybox;
+uniform sampler2D blackbodyLut;  // unit-luminance linear sRGB, evenly spaced in ln T
+uniform sampler2D diskFluxLut;   // Novikov–Thorne F / F_peak, evenly spaced in ln r over u_disk_flux_range
 // Dynamic quality controls (set from the host app)
 uniform int u_max_iter;        // maximum march iterations
 uniform float u_step_scale;    // multiplier applied to STEP_SIZE based on quality
 uniform int u_noise_lod;       // noise LOD (effective max)
 
-// --- Accretion disk parameters (tunable constants copied/approximated from RossNing)
-const float ADISK_INNER = 2.6;
-const float ADISK_OUTER = 12.0;
-// make the disk visibly thicker in world units
-const float ADISK_HEIGHT = 1.0;
-const float ADISK_LIT = 1.0;
-// vertical density exponent: lower -> slower falloff -> thicker appearance
-const float ADISK_DENSITY_V = 2.0;
-const float ADISK_DENSITY_H = 1.0;
-const float ADISK_NOISE_SCALE = 1.0;
+// Accretion disk parameters (DiskParams on the host), lengths in R_S
+uniform float u_disk_inner;          // inner edge, normally the ISCO of u_spin
+uniform float u_disk_outer;
+uniform float u_disk_height;         // half thickness
+uniform float u_disk_density_v;      // vertical density exponent: lower -> thicker appearance
+uniform float u_disk_density_h;      // radial density exponent
+uniform float u_disk_noise_scale;
+uniform float u_disk_speed;
+uniform float u_disk_density_scale;  // visual density/emission scale
+uniform float u_disk_absorption;     // Beer–Lambert attenuation coefficient
+uniform bool u_redshift;             // Doppler beaming + gravitational redshift of the disk
+uniform int u_emission_mode;         // 0 = colour map, 1 = blackbody
+uniform vec2 u_disk_flux_range;      // radii of the first and last diskFluxLut entries
+uniform float u_disk_peak_temperature; // K, hottest ring of the disk before redshift
+
+// --- Remaining accretion disk constants (copied/approximated from RossNing)
+// ADISK_LIT and ADISK_PARTICLE can be overridden with --define NAME=VALUE
+#ifndef ADISK_LIT
+#define ADISK_LIT 1.0
+#endif
 // Reduced noise LOD to cut down on expensive noise calls per-sample
 const int   ADISK_NOISE_LOD = 2;
-const float ADISK_SPEED = 0.5;
-const float ADISK_PARTICLE = 1.0; // when <0.5, use particle-lite fallback
-
-float hash(vec3 p) {
-    p = fract(p * 0.3183099 + 0.1);
-    p *= 17.0;
-    return fract(p.x * p.y * p.z * (p.x + p.y + p.z));
+#ifndef ADISK_PARTICLE
+#define ADISK_PARTICLE 1.0 // when <0.5, use particle-lite fallback
+#endif
+// intensity scales as g^4 for bolometric emission (g^3 at a fixed frequency)
+const float BEAMING_EXPONENT = 4.0;
+// blackbody lookup table range (renderer::blackbody on the host)
+const float LUT_SIZE = 256.0;
+const float LUT_MIN_TEMPERATURE = 1000.0;
+const float LUT_MAX_TEMPERATURE = 40000.0;
+const float FLUX_LUT_SIZE = 256.0;
+
+// Kerr mass in shader units (R_S = 2M)
+const float M = 0.5 * R_S;
+
+float kerrRadius(vec3 x, float a); // defined with the Kerr integrator below
+
+// g = nu_obs / nu_emit for gas on a Keplerian circular orbit about the spin
+// axis at world position pos, seen along a photon with conserved L_z / E =
+// lambda. Combines the Doppler shift of the orbital motion with the
+// gravitational redshift: g = 1 / (u^t * (1 - Omega * lambda)).
+float redshiftFactor(vec3 pos, float lambda) {
+    float a = u_spin * M;
+    vec3 x = pos.zxy;
+    // Boyer–Lindquist r and theta equal the Kerr–Schild spheroidal ones
+    float r = kerrRadius(x, a);
+    float cos2 = x.z * x.z / (r * r);
+    float sin2 = 1.0 - cos2;
+    float sigma = r * r + a * a * cos2;
+    float omega = sqrt(M) / (pow(r, 1.5) + a * sqrt(M));
+
+    float gtt = -(1.0 - 2.0 * M * r / sigma);
+    float gtphi = -2.0 * M * a * r * sin2 / sigma;
+    float gphiphi = (r * r + a * a + 2.0 * M * a * a * r * sin2 / sigma) * sin2;
+    // 1 / (u^t)^2; not positive where no timelike circular orbit exists
+    float norm = -(gtt + 2.0 * omega * gtphi + omega * omega * gphiphi);
+    if (norm <= 0.0) return 0.0;
+    return sqrt(norm) / (1.0 - omega * lambda);
 }
 
 vec3 accel(float h2, vec3 pos) {
@@ -56,76 +100,36 @@ vec3 accel(float h2, vec3 pos) {
 }
 
 
-// --- Simplex noise (ported compactly from RossNing shader)
-vec4 permute(vec4 x) { return mod(((x * 34.0) + 1.0) * x, 289.0); }
-vec4 taylorInvSqrt(vec4 r) { return 1.79284291400159 - 0.85373472095314 * r; }
-float snoise(vec3 v) {
-    const vec2 C = vec2(1.0/6.0, 1.0/3.0);
-    const vec4 D = vec4(0.0, 0.5, 1.0, 2.0);
-
-    // First corner
-    vec3 i  = floor(v + dot(v, C.yyy));
-    vec3 x0 = v - i + dot(i, C.xxx);
-
-    // Other corners
-    vec3 g = step(x0.yzx, x0.xyz);
-    vec3 l = 1.0 - g;
-    vec3 i1 = min(g.xyz, l.zxy);
-    vec3 i2 = max(g.xyz, l.zxy);
-
-    vec3 x1 = x0 - i1 + C.xxx;
-    vec3 x2 = x0 - i2 + C.yyy;
-    vec3 x3 = x0 - D.yyy;
-
-    i = mod(i, 289.0);
-    vec4 p = permute(permute(permute(i.z + vec4(0.0, i1.z, i2.z, 1.0))
-               + i.y + vec4(0.0, i1.y, i2.y, 1.0))
-               + i.x + vec4(0.0, i1.x, i2.x, 1.0));
-
-    vec3 ns = 1.0/7.0 * D.wyz - D.xzx;
-    vec4 j = p - 49.0 * floor(p * ns.z * ns.z);
-    vec4 x_ = floor(j * ns.z);
-    vec4 y_ = floor(j - 7.0 * x_);
-
-    vec4 x = x_ * ns.x + ns.yyyy;
-    vec4 y = y_ * ns.x + ns.yyyy;
-    vec4 h = 1.0 - abs(x) - abs(y);
+#include "common/noise.glsl"
 
-    vec4 b0 = vec4(x.xy, y.xy);
-    vec4 b1 = vec4(x.zw, y.zw);
-
-    vec4 s0 = floor(b0) * 2.0 + 1.0;
-    vec4 s1 = floor(b1) * 2.0 + 1.0;
-    vec4 sh = -step(h, vec4(0.0));
-
-    vec4 a0 = b0.xzyw + s0.xzyw * sh.xxyy;
-    vec4 a1 = b1.xzyw + s1.xzyw * sh.zzww;
-
-    vec3 p0 = vec3(a0.xy, h.x);
-    vec3 p1 = vec3(a0.zw, h.y);
-    vec3 p2 = vec3(a1.xy, h.z);
-    vec3 p3 = vec3(a1.zw, h.w);
-
-    vec4 norm = taylorInvSqrt(vec4(dot(p0,p0), dot(p1,p1), dot(p2,p2), dot(p3,p3)));
-    p0 *= norm.x; p1 *= norm.y; p2 *= norm.z; p3 *= norm.w;
+// Novikov–Thorne flux at radius r (R_S) relative to the hottest ring; zero
+// inside the ISCO
+float diskFlux(float r) {
+    float u = log(r / u_disk_flux_range.x) / log(u_disk_flux_range.y / u_disk_flux_range.x);
+    u = (0.5 + clamp(u, 0.0, 1.0) * (FLUX_LUT_SIZE - 1.0)) / FLUX_LUT_SIZE;
+    return texture(diskFluxLut, vec2(u, 0.5)).r;
+}
 
-    vec4 m = max(0.6 - vec4(dot(x0,x0), dot(x1,x1), dot(x2,x2), dot(x3,x3)), 0.0);
-    m = m * m;
-    return 42.0 * dot(m*m, vec4(dot(p0,x0), dot(p1,x1), dot(p2,x2), dot(p3,x3)));
+vec3 blackbodyColor(float temperature) {
+    float u = log(temperature / LUT_MIN_TEMPERATURE) / log(LUT_MAX_TEMPERATURE / LUT_MIN_TEMPERATURE);
+    // map [0, 1] onto the texel centres
+    u = (0.5 + clamp(u, 0.0, 1.0) * (LUT_SIZE - 1.0)) / LUT_SIZE;
+    return texture(blackbodyLut, vec2(u, 0.5)).rgb;
 }
 
 // volumetric accretion disk color accumulation per-march-step
 // Uses a simple emission + Beer–Lambert attenuation so the disk absorbs
 // light and progressively reduces the ray's alpha (transmittance).
-void adiskColor(in vec3 pos, inout vec3 color, inout float alpha, in float step) {
-    float innerRadius = ADISK_INNER;
-    float outerRadius = ADISK_OUTER;
+// lambda is the photon's L_z / E, used for the redshift of the emission.
+void adiskColor(in vec3 pos, inout vec3 color, inout float alpha, in float step, in float lambda) {
+    float innerRadius = u_disk_inner;
+    float outerRadius = u_disk_outer;
 
     // radial / vertical falloff
-    float density = max(0.0, 1.0 - length(pos.xyz / vec3(outerRadius, ADISK_HEIGHT, outerRadius)));
+    float density = max(0.0, 1.0 - length(pos.xyz / vec3(outerRadius, u_disk_height, outerRadius)));
     if (density < 0.001) return;
 
-    density *= pow(1.0 - abs(pos.y) / ADISK_HEIGHT, ADISK_DENSITY_V);
+    density *= pow(1.0 - abs(pos.y) / u_disk_height, u_disk_density_v);
 
     // mask out inside the innermost stable circular orbit
     density *= smoothstep(innerRadius, innerRadius * 1.1, length(pos.xz));
@@ -136,8 +140,25 @@ void adiskColor(in vec3 pos, inout vec3 color, inout float alpha, in float step)
     float theta = atan(pos.z, pos.x);
     float phi = abs(pos.y);
 
-    // radial UV for color map lookup (map colormap by radius)
-    float v_radial = clamp((rho - innerRadius) / (outerRadius - innerRadius), 0.0, 1.0);
+    float g = u_redshift ? max(redshiftFactor(pos, lambda), 1e-3) : 1.0;
+    vec3 dustColor;
+    float beaming;
+    if (u_emission_mode == 1) {
+        // blackbody colour of the observed temperature g * T with T^4 ~ F;
+        // the brightness is the flux relative to the hottest ring, beamed
+        float flux = diskFlux(rho);
+        float tObserved = g * u_disk_peak_temperature * pow(flux, 0.25);
+        dustColor = blackbodyColor(tObserved);
+        beaming = flux * pow(g, BEAMING_EXPONENT);
+    } else {
+        // The colour map runs from hot to cool with radius. With T ~ r^(-3/4),
+        // a shift of T by g is the colour of the radius rho * g^(-4/3).
+        float shiftedRho = rho * pow(g, -4.0 / 3.0);
+        // radial UV for color map lookup (map colormap by radius)
+        float v_radial = clamp((shiftedRho - innerRadius) / (outerRadius - innerRadius), 0.0, 1.0);
+        dustColor = texture(colorMap, vec2(v_radial, 0.5)).rgb;
+        beaming = pow(g, BEAMING_EXPONENT);
+    }
 
     // radial LOD and noise accumulation (dynamic LOD via uniform u_noise_lod)
     float noise = 1.0;
@@ -145,24 +166,22 @@ void adiskColor(in vec3 pos, inout vec3 color, inout float alpha, in float step)
         if (i >= u_noise_lod) break;
         float f = float(i*i);
         // add time-driven rotation to theta so the disk appears to spin
-        float theta_t = theta + u_time * ADISK_SPEED * 0.5;
-        noise *= 0.5 * snoise(vec3(rho, theta_t, phi) * f * ADISK_NOISE_SCALE) + 0.5;
+        float theta_t = theta + u_time * u_disk_speed * 0.5;
+        noise *= 0.5 * snoise(vec3(rho, theta_t, phi) * f * u_disk_noise_scale) + 0.5;
         // small per-LOD offset to break repetition
-        if (i % 2 == 0) theta += ADISK_SPEED * 0.01; else theta -= ADISK_SPEED * 0.01;
+        if (i % 2 == 0) theta += u_disk_speed * 0.01; else theta -= u_disk_speed * 0.01;
     }
 
-    density *= 1.0 / pow(rho, ADISK_DENSITY_H);
+    density *= 1.0 / pow(rho, u_disk_density_h);
     // tuneable visual scale factor (make larger so the disk is more visible)
-    density *= 160.0;
+    density *= u_disk_density_scale;
 
-    // Simple emission color from the disk (samples the provided colorMap)
+    // Simple emission color from the disk
     if (ADISK_PARTICLE < 0.5) {
-    // particle fallback uses radial mapping for the color strip
-    vec3 dustColor = texture(colorMap, vec2(v_radial, 0.5)).rgb;
         // emission
-        vec3 emission = dustColor * density * 0.04 * abs(noise);
+        vec3 emission = dustColor * density * 0.04 * abs(noise) * beaming;
         // attenuation coefficient (controls how quickly light is absorbed)
-        float sigma = 0.02;
+        float sigma = u_disk_absorption;
         float tau = density * sigma * step;
         float trans = exp(-tau);
         color += (1.0 - trans) * emission * alpha;
@@ -170,15 +189,11 @@ void adiskColor(in vec3 pos, inout vec3 color, inout float alpha, in float step)
         return;
     }
 
-    // Sample colorMap radially (u = radius) so the colormap strip maps outward
-    // from the inner radius to the outer radius.
-    vec3 dustColor = texture(colorMap, vec2(v_radial, 0.5)).rgb;
-
     // emission scaled by density, lighting and noise
-    vec3 emission = density * ADISK_LIT * dustColor * abs(noise);
+    vec3 emission = density * ADISK_LIT * dustColor * abs(noise) * beaming;
     // attenuation coefficient (controls how quickly light is absorbed)
     // reduced so the disk contributes more visible emission per step
-    float sigma = 0.02;
+    float sigma = u_disk_absorption;
     float tau = density * sigma * step;
     float trans = exp(-tau);
     color += (1.0 - trans) * emission * alpha;
@@ -188,8 +203,8 @@ void adiskColor(in vec3 pos, inout vec3 color, inout float alpha, in float step)
 // Disk color now uses texture lookup
 vec3 getDiskColor(vec3 pos) {
     float r = length(pos.xz);
-    // Map radius to [0,1] range (inner radius = ADISK_INNER, outer = ADISK_OUTER)
-    float v = clamp((r - ADISK_INNER) / (ADISK_OUTER - ADISK_INNER), 0.0, 1.0);
+    // Map radius to [0,1] range (inner radius = u_disk_inner, outer = u_disk_outer)
+    float v = clamp((r - u_disk_inner) / (u_disk_outer - u_disk_inner), 0.0, 1.0);
     // Lookup disk color radially from the colormap strip
     vec3 col = texture(colorMap, vec2(v, 0.5)).rgb;
     return col;
@@ -200,7 +215,120 @@ vec3 getSkyboxColor(vec3 dir) {
     return texture(skybox, dir).rgb;
 }
 
+// --- Kerr geodesics in Cartesian Kerr–Schild coordinates (mirrors src/physics/kerr.rs)
+// Regular on the spin axis and at the horizon. The spin axis is world +y and
+// positive spin turns +z towards +x like the disk; the Kerr–Schild frame is
+// (X, Y, Z) = world (z, x, y). The state is position x and momentum p with E = 1.
+
+float kerrRadius(vec3 x, float a) {
+    float b = dot(x, x) - a * a;
+    return sqrt(0.5 * (b + sqrt(b * b + 4.0 * a * a * x.z * x.z)));
+}
+
+// Hamilton's equations for H = 0.5 * (-1 + |p|^2 - f * (1 + l.p)^2)
+void kerrRhs(vec3 x, vec3 p, float a, out vec3 dx, out vec3 dp) {
+    float a2 = a * a;
+    float r = kerrRadius(x, a);
+    float r2 = r * r;
+    float ra2 = r2 + a2;
+    float denom = r2 * r2 + a2 * x.z * x.z;
+    float f = 2.0 * M * r2 * r / denom;
+    vec3 l = vec3((r * x.x + a * x.y) / ra2, (r * x.y - a * x.x) / ra2, x.z / r);
+    float s = 1.0 + dot(l, p);
+
+    vec3 gradR = vec3(r2 * r * x.x, r2 * r * x.y, r * x.z * ra2) / denom;
+    vec3 gradF = (gradR * (3.0 * a2 * x.z * x.z - r2 * r2) - vec3(0.0, 0.0, 2.0 * a2 * r * x.z))
+        * (2.0 * M * r2 / (denom * denom));
+    vec3 gradLp = vec3(r * p.x - a * p.y, a * p.x + r * p.y, 0.0) / ra2
+        + vec3(0.0, 0.0, p.z / r)
+        + gradR * ((p.x * (x.x * ra2 - 2.0 * r * (r * x.x + a * x.y))
+                  + p.y * (x.y * ra2 - 2.0 * r * (r * x.y - a * x.x))) / (ra2 * ra2)
+                  - p.z * x.z / r2);
+
+    dx = p - f * s * l;
+    dp = 0.5 * s * s * gradF + f * s * gradLp;
+}
+
+void kerrStep(inout vec3 x, inout vec3 p, float a, float h) {
+    vec3 dx1, dx2, dx3, dx4;
+    vec3 dp1, dp2, dp3, dp4;
+    kerrRhs(x, p, a, dx1, dp1);
+    kerrRhs(x + 0.5 * h * dx1, p + 0.5 * h * dp1, a, dx2, dp2);
+    kerrRhs(x + 0.5 * h * dx2, p + 0.5 * h * dp2, a, dx3, dp3);
+    kerrRhs(x + h * dx3, p + h * dp3, a, dx4, dp4);
+    x += h / 6.0 * (dx1 + 2.0 * dx2 + 2.0 * dx3 + dx4);
+    p += h / 6.0 * (dp1 + 2.0 * dp2 + 2.0 * dp3 + dp4);
+}
+
+// null momentum with E = 1 whose coordinate velocity points along dir
+// (the camera must sit outside the ergosphere)
+vec3 kerrInitMomentum(vec3 x, vec3 dir, float a) {
+    float r = kerrRadius(x, a);
+    float ra2 = r * r + a * a;
+    float f = 2.0 * M * r * r * r / (r * r * r * r + a * a * x.z * x.z);
+    vec3 l = vec3((r * x.x + a * x.y) / ra2, (r * x.y - a * x.x) / ra2, x.z / r);
+    float q = dot(l, dir);
+    float k = 1.0 / sqrt(1.0 - f * (1.0 - q * q));
+    float s = (1.0 + k * q) / (1.0 - f);
+    return k * dir + f * s * l;
+}
+
+vec3 traceRayKerr(vec3 pos, vec3 dir) {
+    vec3 color = vec3(0.0);
+    float alpha = 1.0;
+
+    float a = u_spin * M;
+    vec3 x = pos.zxy;
+    vec3 p = kerrInitMomentum(x, normalize(dir).zxy, a);
+    // the photon reaching the camera travels opposite to the traced ray
+    float lambda = -(x.x * p.y - x.y * p.x);
+    float rPlus = M + sqrt(max(M * M - a * a, 0.0));
+
+    // same step heuristic as traceRay; far from the hole dλ is ~ path length
+    const float STEP_SIZE = 0.06;
+    float distScale = clamp(length(pos) / 4.0, 0.6, 3.0);
+    float baseStep = STEP_SIZE * distScale * u_step_scale;
+
+    float maxDist = length(pos) + u_disk_outer * 2.0;
+    float traveled = 0.0;
+    vec3 rayDir = normalize(dir);
+
+    const int MAX_CAP = 2000;
+    for (int i = 0; i < MAX_CAP; i++) {
+        if (i >= u_max_iter) break;
+
+        // RK4 in these coordinates needs steps well below r near the horizon
+        float step = min(baseStep, 0.1 * kerrRadius(x, a));
+
+        if (u_render_disk && alpha > 0.001) {
+            adiskColor(x.yzx, color, alpha, step, lambda);
+            if (alpha < 0.001) {
+                return color;
+            }
+        }
+
+        vec3 prev = x;
+        kerrStep(x, p, a, step);
+        float moved = length(x - prev);
+        if (moved > 0.0) rayDir = (x - prev).yzx / moved;
+        traveled += moved;
+
+        if (kerrRadius(x, a) < rPlus) {
+            return color;
+        }
+
+        if (traveled > maxDist) break;
+    }
+
+    color += getSkyboxColor(rayDir) * alpha;
+    return color;
+}
+
 vec3 traceRay(vec3 pos, vec3 dir, vec3 viewDir) {
+    if (u_gravitational_lensing && abs(u_spin) > 1e-4) {
+        return traceRayKerr(pos, dir);
+    }
+
     vec3 color = vec3(0.0);
     float alpha = 1.0;
 
@@ -217,8 +345,11 @@ vec3 traceRay(vec3 pos, vec3 dir, vec3 viewDir) {
     vec3 rayDir = normalize(dir);
     vec3 distortedViewDir = normalize(viewDir);
 
+    // L_z / E of the photon arriving at a static camera along -rayDir
+    float lambda = (pos.x * rayDir.z - pos.z * rayDir.x) / sqrt(max(1.0 - R_S / dist, 1e-4));
+
     // determine maximum travel distance for this ray based on starting distance
-    float maxDist = length(pos) + ADISK_OUTER * 2.0;
+    float maxDist = length(pos) + u_disk_outer * 2.0;
     float traveled = 0.0;
 
     // Use a compile-time cap but break based on the uniform u_max_iter so the host
@@ -234,7 +365,7 @@ vec3 traceRay(vec3 pos, vec3 dir, vec3 viewDir) {
         // accumulate disk color only for samples outside the horizon; pass
         // the current step so adiskColor can attenuate the ray (reduce alpha)
         if (u_render_disk && alpha > 0.001 && dot(pos, pos) >= R_S * R_S) {
-            adiskColor(pos, color, alpha, step);
+            adiskColor(pos, color, alpha, step, lambda);
             // early out if the ray is almost fully attenuated
             if (alpha < 0.001) {
                 return color;
@@ -277,7 +408,8 @@ void main() {
     // camera position: use application-provided camera coordinates directly (camera uses shader units)
     vec3 normalizedCamPos = u_camera_pos;
 
-    vec2 uv = (gl_FragCoord.xy / u_resolution.xy) * 2.0 - 1.0;
+    // u_resolution is the whole image, of which a tiled render draws one tile
+    vec2 uv = ((gl_FragCoord.xy + u_tile_offset) / u_resolution.xy) * 2.0 - 1.0;
     uv.x *= u_resolution.x / u_resolution.y;
 
     float tanHalfFov = tan(radians(u_fov * 0.5));
@@ -285,13 +417,8 @@ void main() {
     dir = u_view_matrix * dir;
     vec3 viewDir = dir; // preserve original view direction for skybox sampling
 
+    // linear radiance; bloom, exposure, tone mapping and gamma follow in
+    // post.frag
     vec3 color = traceRay(normalizedCamPos, dir, viewDir);
-
-    // Simple tone mapping + gamma to avoid extreme overexposure
-    // Reinhard tone mapping
-    color = color / (color + vec3(1.0));
-    // gamma correction
-    color = pow(color, vec3(1.0 / 2.2));
-
     FragColor = vec4(color, 1.0);
 }
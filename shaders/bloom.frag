@@ -0,0 +1,35 @@
+#version 330 core
+
+// Passes of the bloom mip chain (renderer::post on the host). Every pass draws
+// a full-screen quad into one level of the chain.
+
+in vec2 vTexCoord;
+out vec4 FragColor;
+
+uniform sampler2D u_source;
+uniform int u_pass;         // 0 bright pass, 1 blur, 2 upsample
+uniform float u_threshold;  // radiance above which pixels glow
+uniform vec2 u_direction;   // blur step: one texel of u_source along x or y
+
+// 9-tap binomial kernel, an approximation of a gaussian with sigma = 1.4 texels
+const float WEIGHTS[5] = float[](70.0 / 256.0, 56.0 / 256.0, 28.0 / 256.0, 8.0 / 256.0, 1.0 / 256.0);
+
+void main() {
+    if (u_pass == 0) {
+        // keep the part of each pixel's brightness above the threshold
+        vec3 c = texture(u_source, vTexCoord).rgb;
+        float luminance = dot(c, vec3(0.2126, 0.7152, 0.0722));
+        c *= max(luminance - u_threshold, 0.0) / max(luminance, 1e-4);
+        FragColor = vec4(c, 1.0);
+    } else if (u_pass == 1) {
+        vec3 c = WEIGHTS[0] * texture(u_source, vTexCoord).rgb;
+        for (int i = 1; i < 5; i++) {
+            c += WEIGHTS[i] * texture(u_source, vTexCoord + float(i) * u_direction).rgb;
+            c += WEIGHTS[i] * texture(u_source, vTexCoord - float(i) * u_direction).rgb;
+        }
+        FragColor = vec4(c, 1.0);
+    } else {
+        // bilinear upsample, added to the larger level by the blend state
+        FragColor = vec4(texture(u_source, vTexCoord).rgb, 1.0);
+    }
+}
@@ -0,0 +1,12 @@
+#version 330 core
+
+in vec2 vTexCoord;
+in vec4 vColor;
+out vec4 FragColor;
+
+uniform sampler2D u_texture;  // font atlas (white, coverage in alpha) or image
+
+void main() {
+    vec4 texel = vTexCoord.x < 0.0 ? vec4(1.0) : texture(u_texture, vTexCoord);
+    FragColor = vColor * texel;
+}
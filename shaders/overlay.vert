@@ -0,0 +1,19 @@
+#version 330 core
+
+// 2D layer on top of the frame (renderer::overlay on the host), placed in
+// pixels from the top left of the viewport.
+
+layout(location = 0) in vec2 aPos;
+layout(location = 1) in vec2 aTexCoord;  // negative for solid shapes
+layout(location = 2) in vec4 aColor;
+
+uniform vec2 u_viewport;
+
+out vec2 vTexCoord;
+out vec4 vColor;
+
+void main() {
+    gl_Position = vec4(aPos.x / u_viewport.x * 2.0 - 1.0, 1.0 - aPos.y / u_viewport.y * 2.0, 0.0, 1.0);
+    vTexCoord = aTexCoord;
+    vColor = aColor;
+}
@@ -0,0 +1,56 @@
+#version 330 core
+
+// Final pass: adds the bloom to the HDR frame, then applies exposure, tone
+// mapping and gamma (renderer::post and renderer::exposure on the host).
+
+in vec2 vTexCoord;
+out vec4 FragColor;
+
+uniform sampler2D u_scene;     // linear radiance written by blackhole.frag
+uniform sampler2D u_bloom;     // first level of the bloom chain
+uniform vec4 u_bloom_rect;     // offset and size of this frame within u_bloom
+uniform float u_bloom_intensity; // 0 with bloom off
+uniform int u_tonemap;         // 0 Reinhard, 1 extended Reinhard, 2 ACES, 3 Uncharted 2, 4 linear
+uniform float u_exposure;      // linear scale 2^EV applied before the curve
+
+// extended Reinhard white point and Uncharted 2 linear white
+const float WHITE_POINT = 4.0;
+const float UNCHARTED2_WHITE = 11.2;
+
+// John Hable's filmic curve
+vec3 uncharted2Curve(vec3 x) {
+    const float A = 0.15;
+    const float B = 0.50;
+    const float C = 0.10;
+    const float D = 0.20;
+    const float E = 0.02;
+    const float F = 0.30;
+    return (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F;
+}
+
+vec3 tonemap(vec3 c) {
+    if (u_tonemap == 1) {
+        return c * (1.0 + c / (WHITE_POINT * WHITE_POINT)) / (1.0 + c);
+    } else if (u_tonemap == 2) {
+        // Narkowicz's ACES filmic fit
+        return clamp(c * (2.51 * c + 0.03) / (c * (2.43 * c + 0.59) + 0.14), 0.0, 1.0);
+    } else if (u_tonemap == 3) {
+        return uncharted2Curve(2.0 * c) / uncharted2Curve(vec3(UNCHARTED2_WHITE));
+    } else if (u_tonemap == 4) {
+        return clamp(c, 0.0, 1.0);
+    }
+    // Reinhard
+    return c / (c + vec3(1.0));
+}
+
+void main() {
+    vec3 color = texture(u_scene, vTexCoord).rgb;
+    color += u_bloom_intensity * texture(u_bloom, u_bloom_rect.xy + vTexCoord * u_bloom_rect.zw).rgb;
+
+    // exposure and tone mapping to avoid extreme overexposure
+    color = tonemap(color * u_exposure);
+    // gamma correction
+    color = pow(color, vec3(1.0 / 2.2));
+
+    FragColor = vec4(color, 1.0);
+}
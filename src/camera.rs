@@ -263,7 +263,8 @@ impl Camera {
         }
     }
     
-    pub fn set_mode(&mut self, mode: CameraMode) {
+    /// Switch to `mode`, returning the message announcing it.
+    pub fn set_mode(&mut self, mode: CameraMode) -> String {
         if self.camera_type == CameraType::FreeCam {
             match mode {
                 CameraMode::FrontView => {
@@ -302,22 +303,22 @@ impl Camera {
         }
         
         self.mode = mode;
-        println!("Camera mode: {:?}", match mode {
+        format!("Camera mode: {}", match mode {
             CameraMode::FreeOrbit => "Free Orbit",
             CameraMode::AutoOrbit => "Auto Orbit",
             CameraMode::FrontView => "Front View",
             CameraMode::TopView => "Top View",
-        });
+        })
     }
     
-    pub fn adjust_roll(&mut self, delta: f32) {
+    pub fn adjust_roll(&mut self, delta: f32) -> String {
         self.roll += delta;
-        println!("Camera roll: {:.1}°", self.roll.to_degrees());
+        format!("Camera roll: {:.1}°", self.roll.to_degrees())
     }
 
-    pub fn reset_roll(&mut self) {
+    pub fn reset_roll(&mut self) -> String {
         self.roll = 0.0;
-        println!("Camera roll reset");
+        "Camera roll reset".to_string()
     }
 
     pub fn passive_mouse_move(&mut self, x: f64, y: f64) {
@@ -381,7 +382,8 @@ impl Camera {
         self.free_position = self.free_position + movement;
     }
 
-    pub fn toggle_camera_type(&mut self) {
+    /// Switch between FreeCam and LockedCam, returning the message announcing it.
+    pub fn toggle_camera_type(&mut self) -> String {
         match self.camera_type {
             CameraType::LockedCam => {
                 let current_pos = self.get_position();
@@ -398,7 +400,7 @@ impl Camera {
                 self.elevation = self.elevation.clamp(0.01, PI - 0.01);
                 
                 self.camera_type = CameraType::FreeCam;
-                println!("Camera type: FreeCam");
+                "Camera type: FreeCam".to_string()
             }
             CameraType::FreeCam => {
                 let pos = match self.mode {
@@ -425,7 +427,7 @@ impl Camera {
                     self.mode = CameraMode::FreeOrbit;
                 }
                 self.camera_type = CameraType::LockedCam;
-                println!("Camera type: LockedCam");
+                "Camera type: LockedCam".to_string()
             }
         }
     }
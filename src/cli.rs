@@ -0,0 +1,441 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::physics::black_hole::parse_mass;
+use crate::renderer::disk::EmissionMode;
+use crate::renderer::exposure::Tonemap;
+use crate::renderer::frame::Quality;
+use crate::renderer::poster::DEFAULT_TILE_SIZE;
+use crate::renderer::recorder::{RecordTarget, DEFAULT_RECORD_FPS};
+use crate::shader::Define;
+
+pub const USAGE: &str = "\
+Usage: blackhole [OPTIONS]
+
+Window:
+  --width <PX>              Window or --headless image width [default: 1920]
+  --height <PX>             Window or --headless image height [default: 1080]
+  --fullscreen              Start in fullscreen mode
+  --no-vsync                Do not wait for vertical sync between frames
+
+Assets:
+  --skybox <DIR>            Folder with right/left/top/bottom/front/back.png
+                            [default: assets/skybox_nebula_dark]
+  --color-map <PATH>        Disk colour map [default: assets/color_map.png]
+  --vertex-shader <PATH>    [default: shaders/blackhole.vert]
+  --fragment-shader <PATH>  [default: shaders/blackhole.frag]
+  --define <NAME[=VALUE]>   Define a macro in the main shaders, e.g.
+                            ADISK_LIT=2.0; may be repeated [default value: 1]
+
+View:
+  --scene <PATH>            Start from a scene file saved with the S key;
+                            the options below override values in it
+  --from-image <PNG>        Start from the scene embedded in a saved render,
+                            like --scene, at the size of that image unless
+                            --width or --height is given
+  --radius <R>              Camera distance from the hole in r_s [default: 15]
+  --azimuth <DEG>           Camera azimuth around the spin axis [default: 45]
+  --elevation <DEG>         Camera angle from the spin axis, 90 = disk plane
+                            [default: 81]
+  --fov <DEG>               Vertical field of view [default: 60]
+  --spin <A>                Dimensionless spin a/M in (-1, 1) [default: 0]
+  --mass <M>                Mass in solar masses, or one of the presets
+                            stellar, sgr_a, m87 [default: sgr_a]
+  --no-disk                 Start with the accretion disk hidden
+  --no-lensing              Start with gravitational lensing off
+  --no-redshift             Start with Doppler beaming and redshift of the
+                            disk off
+  --emission <MODE>         Disk colouring: color_map or blackbody
+                            [default: color_map]
+  --quality <PRESET>        low, medium, high or ultra [default: medium]
+  --tonemap <OP>            reinhard, reinhard_extended, aces, uncharted2 or
+                            linear [default: reinhard]
+  --exposure <EV>           Exposure in stops, or the compensation on top of
+                            --auto-exposure [default: 0]
+  --auto-exposure           Expose for the average brightness of the view
+  --no-bloom                Start without the glow around bright areas
+
+Headless rendering:
+  --headless                Render one frame offscreen, save it and exit
+  --backend <gpu|cpu>       Renderer used by --headless [default: gpu]
+  -o, --output <PATH>       Image written by --headless [default: render.png];
+                            .exr and .hdr files get the linear radiance
+                            before bloom and tone mapping
+  --time <SECONDS>          Shader time used for the disk animation [default: 0]
+  --tile-size <PX>          Largest piece the gpu backend draws at once; larger
+                            images are rendered in tiles and stitched
+                            [default: 2048]
+
+Animation:
+  --timeline <PATH>         Render every frame of a keyframe timeline offscreen
+                            with the --headless backend and exit; #s in the
+                            --output name become the frame number
+  --fps <N>                 Frame rate of the --timeline export, overriding
+                            the one in the file [default: 30]
+
+Recording (the Y key, or --timeline with a .y4m --output):
+  --record <PATH>           YUV4MPEG2 file recordings are written to
+                            [default: recordings/recording_<time>.y4m]
+  --record-pipe <COMMAND>   Stream recordings to the stdin of a shell command
+                            instead, e.g. \"ffmpeg -y -i - video.mp4\"
+  --record-fps <N>          Frame rate of recordings in the app [default: 30]
+  --record-size <WxH>       Size of recorded frames in the app; other window
+                            sizes are scaled to it [default: window size]
+
+  -h, --help                Print this help
+
+The gpu backend still needs an OpenGL context, so on machines without a
+display run it under a virtual X server such as Xvfb, or use --backend cpu.";
+
+/// Which renderer draws `--headless` frames.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Backend {
+    /// `blackhole.frag` drawn into an offscreen framebuffer.
+    Gpu,
+    /// The multithreaded port in `renderer::cpu`; needs no OpenGL context.
+    Cpu,
+}
+
+impl Backend {
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Gpu => "gpu",
+            Backend::Cpu => "cpu",
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "gpu" => Ok(Backend::Gpu),
+            "cpu" => Ok(Backend::Cpu),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Settings taken from the command line. Anything not given keeps the value
+/// the interactive renderer has always started with.
+pub struct Options {
+    pub help: bool,
+    pub width: u32,
+    pub height: u32,
+    /// Whether `--width` or `--height` was given.
+    pub custom_size: bool,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    pub skybox: PathBuf,
+    pub color_map: PathBuf,
+    pub vertex_shader: PathBuf,
+    pub fragment_shader: PathBuf,
+    /// Macros injected into the main shader pair.
+    pub defines: Vec<Define>,
+    pub scene: Option<PathBuf>,
+    /// PNG whose embedded scene to start from.
+    pub from_image: Option<PathBuf>,
+    pub radius: Option<f32>,
+    pub azimuth: Option<f32>,
+    pub elevation: Option<f32>,
+    pub fov: Option<f32>,
+    pub spin: Option<f64>,
+    /// Mass in solar masses.
+    pub mass: Option<f64>,
+    pub render_disk: Option<bool>,
+    pub redshift: Option<bool>,
+    pub emission_mode: Option<EmissionMode>,
+    pub gravitational_lensing: Option<bool>,
+    pub quality: Option<Quality>,
+    pub tonemap: Option<Tonemap>,
+    /// Exposure in EV.
+    pub exposure: Option<f32>,
+    pub auto_exposure: Option<bool>,
+    pub bloom: Option<bool>,
+    pub headless: bool,
+    pub backend: Backend,
+    pub output: PathBuf,
+    pub time: Option<f32>,
+    /// Largest tile of a `--headless` GPU render, in pixels.
+    pub tile_size: u32,
+    /// Keyframe timeline to export as numbered frames.
+    pub timeline: Option<PathBuf>,
+    pub fps: Option<f64>,
+    pub record: Option<PathBuf>,
+    /// Shell command recordings are piped to.
+    pub record_pipe: Option<String>,
+    pub record_fps: f64,
+    pub record_size: Option<(u32, u32)>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            help: false,
+            width: 1920,
+            height: 1080,
+            custom_size: false,
+            fullscreen: false,
+            vsync: true,
+            skybox: PathBuf::from("assets/skybox_nebula_dark"),
+            color_map: PathBuf::from("assets/color_map.png"),
+            vertex_shader: PathBuf::from("shaders/blackhole.vert"),
+            fragment_shader: PathBuf::from("shaders/blackhole.frag"),
+            defines: Vec::new(),
+            scene: None,
+            from_image: None,
+            radius: None,
+            azimuth: None,
+            elevation: None,
+            fov: None,
+            spin: None,
+            mass: None,
+            render_disk: None,
+            redshift: None,
+            emission_mode: None,
+            gravitational_lensing: None,
+            quality: None,
+            tonemap: None,
+            exposure: None,
+            auto_exposure: None,
+            bloom: None,
+            headless: false,
+            backend: Backend::Gpu,
+            output: PathBuf::from("render.png"),
+            time: None,
+            tile_size: DEFAULT_TILE_SIZE,
+            timeline: None,
+            fps: None,
+            record: None,
+            record_pipe: None,
+            record_fps: DEFAULT_RECORD_FPS,
+            record_size: None,
+        }
+    }
+}
+
+impl Options {
+    /// Parse the arguments following the program name.
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut opts = Self::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => opts.help = true,
+                "--width" => {
+                    opts.width = value(&arg, &mut args)?;
+                    opts.custom_size = true;
+                }
+                "--height" => {
+                    opts.height = value(&arg, &mut args)?;
+                    opts.custom_size = true;
+                }
+                "--fullscreen" => opts.fullscreen = true,
+                "--no-vsync" => opts.vsync = false,
+                "--skybox" => opts.skybox = value(&arg, &mut args)?,
+                "--color-map" => opts.color_map = value(&arg, &mut args)?,
+                "--vertex-shader" => opts.vertex_shader = value(&arg, &mut args)?,
+                "--fragment-shader" => opts.fragment_shader = value(&arg, &mut args)?,
+                "--define" => {
+                    let define: String = value(&arg, &mut args)?;
+                    opts.defines.push(parse_define(&define).ok_or_else(|| format!("invalid value '{}' for --define", define))?);
+                }
+                "--scene" => opts.scene = Some(value(&arg, &mut args)?),
+                "--from-image" => opts.from_image = Some(value(&arg, &mut args)?),
+                "--radius" => opts.radius = Some(value(&arg, &mut args)?),
+                "--azimuth" => opts.azimuth = Some(value(&arg, &mut args)?),
+                "--elevation" => opts.elevation = Some(value(&arg, &mut args)?),
+                "--fov" => opts.fov = Some(value(&arg, &mut args)?),
+                "--spin" => opts.spin = Some(value(&arg, &mut args)?),
+                "--mass" => {
+                    let mass: String = value(&arg, &mut args)?;
+                    opts.mass = Some(parse_mass(&mass).ok_or_else(|| format!("invalid value '{}' for --mass", mass))?);
+                }
+                "--no-disk" => opts.render_disk = Some(false),
+                "--no-redshift" => opts.redshift = Some(false),
+                "--emission" => opts.emission_mode = Some(value(&arg, &mut args)?),
+                "--no-lensing" => opts.gravitational_lensing = Some(false),
+                "--quality" => opts.quality = Some(value(&arg, &mut args)?),
+                "--tonemap" => opts.tonemap = Some(value(&arg, &mut args)?),
+                "--exposure" => opts.exposure = Some(value(&arg, &mut args)?),
+                "--auto-exposure" => opts.auto_exposure = Some(true),
+                "--no-bloom" => opts.bloom = Some(false),
+                "--headless" => opts.headless = true,
+                "--backend" => opts.backend = value(&arg, &mut args)?,
+                "-o" | "--output" => opts.output = value(&arg, &mut args)?,
+                "--time" => opts.time = Some(value(&arg, &mut args)?),
+                "--tile-size" => opts.tile_size = value(&arg, &mut args)?,
+                "--timeline" => opts.timeline = Some(value(&arg, &mut args)?),
+                "--fps" => opts.fps = Some(value(&arg, &mut args)?),
+                "--record" => opts.record = Some(value(&arg, &mut args)?),
+                "--record-pipe" => opts.record_pipe = Some(value(&arg, &mut args)?),
+                "--record-fps" => opts.record_fps = value(&arg, &mut args)?,
+                "--record-size" => {
+                    let size: String = value(&arg, &mut args)?;
+                    opts.record_size = Some(parse_size(&size).ok_or_else(|| format!("invalid value '{}' for --record-size", size))?);
+                }
+                _ => return Err(format!("unknown argument '{}'", arg)),
+            }
+        }
+
+        if opts.width == 0 || opts.height == 0 {
+            return Err("--width and --height must be positive".to_string());
+        }
+        if opts.scene.is_some() && opts.from_image.is_some() {
+            return Err("--scene and --from-image cannot be combined".to_string());
+        }
+        if opts.timeline.is_some() && (opts.scene.is_some() || opts.from_image.is_some()) {
+            return Err("--timeline cannot be combined with --scene or --from-image; set timeline.scene in the file".to_string());
+        }
+        if opts.fps.is_some_and(|fps| !(fps.is_finite() && fps > 0.0)) {
+            return Err("--fps must be positive".to_string());
+        }
+        if opts.record.is_some() && opts.record_pipe.is_some() {
+            return Err("--record and --record-pipe cannot be combined".to_string());
+        }
+        if !(opts.record_fps.is_finite() && opts.record_fps > 0.0) {
+            return Err("--record-fps must be positive".to_string());
+        }
+        if opts.tile_size == 0 {
+            return Err("--tile-size must be positive".to_string());
+        }
+        if opts.spin.is_some_and(|spin| spin.is_nan() || spin.abs() >= 1.0) {
+            return Err("--spin must lie strictly between -1 and 1".to_string());
+        }
+        if opts.exposure.is_some_and(|ev| !ev.is_finite()) {
+            return Err("--exposure must be a finite number of stops".to_string());
+        }
+        if opts.fov.is_some_and(|fov| fov.is_nan() || fov <= 0.0 || fov >= 180.0) {
+            return Err("--fov must lie between 0 and 180 degrees".to_string());
+        }
+        if opts.radius.is_some_and(|radius| !(radius.is_finite() && radius > 0.0)) {
+            return Err("--radius must be positive".to_string());
+        }
+        if opts.azimuth.is_some_and(|azimuth| !azimuth.is_finite()) {
+            return Err("--azimuth must be a finite number of degrees".to_string());
+        }
+        if opts.elevation.is_some_and(|elevation| !elevation.is_finite()) {
+            return Err("--elevation must be a finite number of degrees".to_string());
+        }
+        Ok(opts)
+    }
+
+    /// Where recordings go, if `--record` or `--record-pipe` was given.
+    pub fn record_target(&self) -> Option<RecordTarget> {
+        match (&self.record, &self.record_pipe) {
+            (Some(path), _) => Some(RecordTarget::File(path.clone())),
+            (None, Some(command)) => Some(RecordTarget::Command(command.clone())),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Parse a size given as `WIDTHxHEIGHT`, both positive.
+fn parse_size(raw: &str) -> Option<(u32, u32)> {
+    let (width, height) = raw.split_once(['x', 'X'])?;
+    let size = (width.trim().parse().ok()?, height.trim().parse().ok()?);
+    (size.0 > 0 && size.1 > 0).then_some(size)
+}
+
+/// Parse a macro given as `NAME` or `NAME=VALUE`; a bare name is defined
+/// as 1.
+fn parse_define(raw: &str) -> Option<Define> {
+    let (name, value) = raw.split_once('=').unwrap_or((raw, "1"));
+    let mut chars = name.chars();
+    let identifier = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    (identifier && !value.contains('\n')).then(|| (name.to_string(), value.to_string()))
+}
+
+/// Parse the value following `flag`.
+fn value<T: FromStr, I: Iterator<Item = String>>(flag: &str, args: &mut I) -> Result<T, String> {
+    let raw = args.next().ok_or_else(|| format!("{} expects a value", flag))?;
+    raw.parse().map_err(|_| format!("invalid value '{}' for {}", raw, flag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn defaults_without_arguments() {
+        let opts = parse(&[]).unwrap();
+        assert_eq!((opts.width, opts.height, opts.custom_size), (1920, 1080, false));
+        assert!(opts.vsync && !opts.headless && !opts.help);
+        assert_eq!(opts.backend, Backend::Gpu);
+        assert_eq!(opts.output, PathBuf::from("render.png"));
+        assert_eq!((opts.tile_size, opts.record_fps), (DEFAULT_TILE_SIZE, DEFAULT_RECORD_FPS));
+        assert!(opts.radius.is_none() && opts.spin.is_none() && opts.record_target().is_none());
+    }
+
+    #[test]
+    fn reads_every_value_flag() {
+        let opts = parse(&[
+            "--width", "640", "--height", "480", "--skybox", "sky", "--color-map", "map.png",
+            "--vertex-shader", "a.vert", "--fragment-shader", "a.frag", "--define", "ADISK_LIT=2.0",
+            "--define", "FAST", "--scene", "s.toml", "--radius", "12.5", "--azimuth", "-30",
+            "--elevation", "90", "--fov", "45", "--spin", "-0.5", "--mass", "m87", "--emission", "blackbody",
+            "--quality", "ultra", "--tonemap", "aces", "--exposure", "1.5", "--backend", "cpu", "-o", "out.exr",
+            "--time", "3", "--tile-size", "512", "--fps", "24", "--record-pipe", "cat", "--record-fps", "60",
+            "--record-size", "320x240",
+        ])
+        .unwrap();
+        assert_eq!((opts.width, opts.height, opts.custom_size), (640, 480, true));
+        assert_eq!((opts.skybox, opts.color_map), (PathBuf::from("sky"), PathBuf::from("map.png")));
+        assert_eq!((opts.vertex_shader, opts.fragment_shader), (PathBuf::from("a.vert"), PathBuf::from("a.frag")));
+        let define = |name: &str, value: &str| (name.to_string(), value.to_string());
+        assert_eq!(opts.defines, vec![define("ADISK_LIT", "2.0"), define("FAST", "1")]);
+        assert_eq!(opts.scene, Some(PathBuf::from("s.toml")));
+        assert_eq!((opts.radius, opts.azimuth, opts.elevation, opts.fov), (Some(12.5), Some(-30.0), Some(90.0), Some(45.0)));
+        assert_eq!((opts.spin, opts.mass), (Some(-0.5), parse_mass("m87")));
+        assert_eq!(opts.emission_mode, Some(EmissionMode::Blackbody));
+        assert_eq!((opts.quality, opts.tonemap, opts.exposure), (Some(Quality::Ultra), Some(Tonemap::Aces), Some(1.5)));
+        assert_eq!((opts.backend, opts.output, opts.time), (Backend::Cpu, PathBuf::from("out.exr"), Some(3.0)));
+        assert_eq!((opts.tile_size, opts.fps, opts.record_fps), (512, Some(24.0), 60.0));
+        assert_eq!((opts.record_pipe.as_deref(), opts.record_size), (Some("cat"), Some((320, 240))));
+
+        let opts = parse(&["--from-image", "a.png", "--record", "r.y4m"]).unwrap();
+        assert_eq!(opts.from_image, Some(PathBuf::from("a.png")));
+        assert!(matches!(opts.record_target(), Some(RecordTarget::File(path)) if path == Path::new("r.y4m")));
+        assert_eq!(parse(&["--timeline", "t.toml"]).unwrap().timeline, Some(PathBuf::from("t.toml")));
+    }
+
+    #[test]
+    fn rejects_missing_values_and_unknown_flags() {
+        assert_eq!(parse(&["--width"]).err().unwrap(), "--width expects a value");
+        assert_eq!(parse(&["--width", "wide"]).err().unwrap(), "invalid value 'wide' for --width");
+        assert_eq!(parse(&["--widht", "640"]).err().unwrap(), "unknown argument '--widht'");
+        assert!(parse(&["--define", "2X=1"]).is_err());
+        assert!(parse(&["--mass", "heavy"]).is_err());
+        assert!(parse(&["--record-size", "0x240"]).is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        for args in [
+            &["--spin", "1.0"][..],
+            &["--spin", "NaN"],
+            &["--fov", "0"],
+            &["--fov", "180"],
+            &["--fps", "0"],
+            &["--record", "a.y4m", "--record-pipe", "cat"],
+            &["--tile-size", "0"],
+            &["--record-fps", "-1"],
+            &["--exposure", "inf"],
+            &["--radius", "0"],
+            &["--radius", "inf"],
+            &["--azimuth", "nan"],
+            &["--elevation", "nan"],
+            &["--width", "0"],
+            &["--scene", "a.toml", "--from-image", "b.png"],
+            &["--timeline", "t.toml", "--scene", "a.toml"],
+        ] {
+            assert!(parse(args).is_err(), "{:?}", args);
+        }
+    }
+}
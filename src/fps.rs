@@ -1,8 +1,17 @@
+use std::collections::VecDeque;
 use std::time::Instant;
 
+/// Number of frame times kept for the HUD graph.
+pub const HISTORY: usize = 120;
+
 pub struct FpsCounter {
     last_time: Instant,
     frame_count: u32,
+    last_frame: Instant,
+    /// Frames per second over the last full second.
+    pub fps: f32,
+    /// Seconds between the latest frames, oldest first.
+    pub frame_times: VecDeque<f32>,
 }
 
 impl FpsCounter {
@@ -10,19 +19,26 @@ impl FpsCounter {
         Self {
             last_time: Instant::now(),
             frame_count: 0,
+            last_frame: Instant::now(),
+            fps: 0.0,
+            frame_times: VecDeque::with_capacity(HISTORY),
         }
     }
 
     pub fn update(&mut self) {
         self.frame_count += 1;
         let now = Instant::now();
-        let elapsed = now.duration_since(self.last_time);
+        if self.frame_times.len() == HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(now.duration_since(self.last_frame).as_secs_f32());
+        self.last_frame = now;
 
+        let elapsed = now.duration_since(self.last_time);
         if elapsed.as_secs_f32() >= 1.0 {
-            let fps = self.frame_count as f32 / elapsed.as_secs_f32();
-            println!("FPS: {:.1}", fps);
+            self.fps = self.frame_count as f32 / elapsed.as_secs_f32();
             self.frame_count = 0;
             self.last_time = now;
         }
     }
-}
\ No newline at end of file
+}
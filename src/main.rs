@@ -1,13 +1,116 @@
 mod gl_bindings;
 
 mod camera;
+mod cli;
 mod shader;
 mod fps;
 mod renderer;
+mod scene;
+mod physics;
+mod metadata;
+mod timeline;
 
+use cli::{Backend, Options};
 use renderer::app::App;
+use renderer::cpu::CpuRenderer;
+use metadata::{shader_hash, RenderMetadata};
+use scene::Scene;
+use timeline::Timeline;
 
 fn main() {
-	let mut app = App::new();
+	let mut options = match Options::parse(std::env::args().skip(1)) {
+		Ok(options) => options,
+		Err(e) => {
+			eprintln!("error: {}\n\n{}", e, cli::USAGE);
+			std::process::exit(2);
+		}
+	};
+	if options.help {
+		println!("{}", cli::USAGE);
+		return;
+	}
+
+	if let Some(path) = &options.timeline {
+		let start = std::time::Instant::now();
+		let result = Timeline::load(path).and_then(|mut timeline| {
+			if let Some(fps) = options.fps {
+				timeline.fps = fps;
+			}
+			timeline::export(&timeline, &options)?;
+			Ok(timeline.frame_count())
+		});
+		match result {
+			Ok(frames) => println!(
+				"Rendered {} frames of {}x{} to {} in {:.2?}",
+				frames,
+				options.width,
+				options.height,
+				options.output.display(),
+				start.elapsed()
+			),
+			Err(e) => {
+				eprintln!("ERROR: {}", e);
+				std::process::exit(1);
+			}
+		}
+		return;
+	}
+
+	let scene = match Scene::from_options(&options) {
+		Ok(scene) => scene,
+		Err(e) => {
+			eprintln!("ERROR: {}", e);
+			std::process::exit(1);
+		}
+	};
+
+	if let Some(path) = &options.from_image {
+		// Scene::from_options has already read the embedded scene
+		if let Ok(metadata) = RenderMetadata::read(path) {
+			let hash = shader_hash(&options.vertex_shader, &options.fragment_shader, &options.defines);
+			let backend = if options.headless { options.backend } else { Backend::Gpu };
+			for difference in metadata.differences(&hash, backend) {
+				eprintln!("WARNING: {} may not be reproduced exactly: {}", path.display(), difference);
+			}
+		}
+		if !options.custom_size {
+			match image::image_dimensions(path) {
+				Ok((width, height)) => (options.width, options.height) = (width, height),
+				Err(e) => {
+					eprintln!("ERROR: Failed to read {}: {}", path.display(), e);
+					std::process::exit(1);
+				}
+			}
+		}
+	}
+
+	if options.headless {
+		let start = std::time::Instant::now();
+		let result = match options.backend {
+			Backend::Gpu => App::headless(&options, &scene).and_then(|mut app| {
+				app.render_to_file(options.width, options.height, scene.time, options.tile_size, &options.output)
+			}),
+			Backend::Cpu => CpuRenderer::load(&options.skybox, &options.color_map, &options.defines).and_then(|cpu| {
+				let hash = shader_hash(&options.vertex_shader, &options.fragment_shader, &options.defines);
+				cpu.render_to_file(&scene, options.width, options.height, &options.output, hash)
+			}),
+		};
+		match result {
+			Ok(()) => println!(
+				"Rendered {}x{} to {} in {:.2?}",
+				options.width,
+				options.height,
+				options.output.display(),
+				start.elapsed()
+			),
+			Err(e) => {
+				eprintln!("ERROR: {}", e);
+				std::process::exit(1);
+			}
+		}
+		return;
+	}
+
+	let mut app = App::new(&options, &scene);
 	app.run();
 }
@@ -0,0 +1,182 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+use crate::cli::Backend;
+use crate::renderer::post::SHADERS;
+use crate::scene::Scene;
+use crate::shader::{preprocess, Define};
+
+/// tEXt keyword holding the scene as a scene file.
+const SCENE_KEY: &str = "blackhole.scene";
+/// tEXt keyword holding `shader_hash` of the shaders that drew the image.
+const SHADER_KEY: &str = "blackhole.shader";
+/// tEXt keyword holding the `Backend` that drew the image.
+const BACKEND_KEY: &str = "blackhole.backend";
+/// tEXt keyword holding the app version.
+const VERSION_KEY: &str = "blackhole.version";
+
+/// Version of this build, stored with every render.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// What a saved render was made from. PNG files carry it in tEXt chunks, so
+/// `--from-image` can draw the same image again.
+#[derive(Clone)]
+pub struct RenderMetadata {
+    /// View and settings, with the shader time of the saved frame.
+    pub scene: Scene,
+    pub shader_hash: String,
+    pub backend: Backend,
+    pub version: String,
+}
+
+impl RenderMetadata {
+    /// Metadata for a render of `scene` made by this build.
+    pub fn new(scene: Scene, shader_hash: String, backend: Backend) -> Self {
+        Self { scene, shader_hash, backend, version: VERSION.to_string() }
+    }
+
+    /// Read the metadata embedded in a PNG saved by `save_png`.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        Self::decode(BufReader::new(file)).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// `read` for a PNG stream.
+    fn decode<R: BufRead + Seek>(png: R) -> Result<Self, String> {
+        let reader = png::Decoder::new(png).read_info().map_err(|e| format!("Failed to read PNG: {}", e))?;
+        let text = |key: &str| {
+            reader.info().uncompressed_latin1_text.iter().find(|chunk| chunk.keyword == key).map(|chunk| chunk.text.clone())
+        };
+
+        let scene = text(SCENE_KEY).ok_or("no embedded scene")?;
+        let scene = Scene::parse(&scene).map_err(|e| format!("embedded scene: {}", e))?;
+        let backend = match text(BACKEND_KEY) {
+            Some(backend) => backend.parse().map_err(|_| format!("unknown backend \"{}\"", backend))?,
+            None => Backend::Gpu,
+        };
+        Ok(Self {
+            scene,
+            shader_hash: text(SHADER_KEY).unwrap_or_default(),
+            backend,
+            version: text(VERSION_KEY).unwrap_or_default(),
+        })
+    }
+
+    /// Save top-to-bottom RGBA rows as a PNG with the metadata in tEXt chunks.
+    pub fn save_png(&self, path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        self.encode(BufWriter::new(file), width, height, pixels)
+            .map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+    }
+
+    /// `save_png` to a stream.
+    fn encode<W: Write>(&self, out: W, width: u32, height: u32, pixels: &[u8]) -> Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(out, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let chunks = [
+            ("Software", format!("blackhole {}", self.version)),
+            ("Creation Time", Local::now().to_rfc2822()),
+            (SCENE_KEY, self.scene.to_toml()),
+            (SHADER_KEY, self.shader_hash.clone()),
+            (BACKEND_KEY, self.backend.name().to_string()),
+            (VERSION_KEY, self.version.clone()),
+        ];
+        for (keyword, text) in chunks {
+            encoder.add_text_chunk(keyword.to_string(), text)?;
+        }
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(pixels)?;
+        writer.finish()
+    }
+
+    /// Save the scene next to the image at `image`, as the scene file of the
+    /// same name, and return its path.
+    pub fn save_sidecar(&self, image: &Path) -> Result<PathBuf, String> {
+        let path = image.with_extension("toml");
+        self.scene.save(&path)?;
+        Ok(path)
+    }
+
+    /// Ways in which this build would draw the image differently than the one
+    /// that saved it, given `shader_hash` of the current shaders and the
+    /// `backend` that will draw it.
+    pub fn differences(&self, shader_hash: &str, backend: Backend) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.version != VERSION {
+            differences.push(format!("it was saved by version {}, this is {}", self.version, VERSION));
+        }
+        if self.shader_hash != shader_hash {
+            differences.push("its shaders differ from the current ones".to_string());
+        }
+        if self.backend != backend {
+            differences.push(format!("it was drawn by the {} backend, this is {}", self.backend.name(), backend.name()));
+        }
+        differences
+    }
+}
+
+/// Fingerprint of the sources of the main shader pair, with its includes
+/// and `defines`, and of the post-processing shaders, which together decide
+/// how a frame looks. Unreadable files count as empty.
+pub fn shader_hash(vertex: &Path, fragment: &Path, defines: &[Define]) -> String {
+    // 64-bit FNV-1a, which unlike std's hasher is the same in every build
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let main = [vertex, fragment].into_iter().map(|path| (path, defines));
+    let post = SHADERS.iter().map(|path| (Path::new(path), &[][..]));
+    for (path, defines) in main.chain(post) {
+        let source = preprocess(path, defines).map(|source| source.text).unwrap_or_default();
+        for byte in source.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn round_trips_through_png_text_chunks() {
+        let mut scene = Scene::default();
+        scene.black_hole.spin = 0.7;
+        scene.fov = 35.0;
+        scene.time = 12.5;
+        let metadata = RenderMetadata::new(scene, "0123456789abcdef".to_string(), Backend::Cpu);
+
+        let mut png = Vec::new();
+        metadata.encode(&mut png, 2, 1, &[255; 8]).unwrap();
+        let read = RenderMetadata::decode(Cursor::new(png)).unwrap();
+        assert_eq!(read.scene.to_toml(), metadata.scene.to_toml());
+        assert_eq!((read.shader_hash.as_str(), read.backend, read.version.as_str()), ("0123456789abcdef", Backend::Cpu, VERSION));
+        assert!(read.differences("0123456789abcdef", Backend::Cpu).is_empty());
+    }
+
+    #[test]
+    fn images_without_a_scene_are_rejected() {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, 1, 1);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.write_header().unwrap().write_image_data(&[0; 4]).unwrap();
+        assert_eq!(RenderMetadata::decode(Cursor::new(png)).err().unwrap(), "no embedded scene");
+    }
+
+    #[test]
+    fn reports_version_shader_and_backend_differences() {
+        let mut metadata = RenderMetadata::new(Scene::default(), "aaaa".to_string(), Backend::Gpu);
+        metadata.version = "0.0.1".to_string();
+        let differences = metadata.differences("bbbb", Backend::Cpu);
+        assert_eq!(differences.len(), 3, "{:?}", differences);
+        assert!(differences[0].contains("version 0.0.1"));
+        assert!(differences[1].contains("shaders differ"));
+        assert!(differences[2].contains("gpu backend"));
+    }
+}
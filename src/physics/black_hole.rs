@@ -0,0 +1,181 @@
+use std::fmt;
+
+use super::kerr::Kerr;
+
+/// Newton's gravitational constant in m³ kg⁻¹ s⁻².
+pub const G: f64 = 6.67430e-11;
+/// Speed of light in m/s.
+pub const C: f64 = 299792458.0;
+/// Solar mass in kg.
+pub const M_SUN: f64 = 1.98847e30;
+
+const AU: f64 = 1.495978707e11;
+const LIGHT_YEAR: f64 = 9.4607304725808e15;
+const PARSEC: f64 = 3.085677581491367e16;
+
+/// A named black hole mass the app can switch to.
+pub struct Preset {
+    /// Name accepted by `--mass` and printed by the app.
+    pub key: &'static str,
+    pub name: &'static str,
+    /// Mass in solar masses.
+    pub mass: f64,
+}
+
+pub const PRESETS: [Preset; 3] = [
+    Preset { key: "stellar", name: "Stellar-mass (10 M☉)", mass: 10.0 },
+    // GRAVITY Collaboration (2023)
+    Preset { key: "sgr_a", name: "Sgr A*", mass: 4.297e6 },
+    // Event Horizon Telescope (2019)
+    Preset { key: "m87", name: "M87*", mass: 6.5e9 },
+];
+
+/// Physical parameters of the hole.
+///
+/// Everything else works in units of the Schwarzschild radius (`r_s = 1`) and
+/// `r_s/c`, which makes the geometry independent of the mass; the mass only
+/// sets how those units map to metres and seconds.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BlackHole {
+    /// Mass in solar masses.
+    pub mass: f64,
+    /// Dimensionless Kerr spin `a/M`; 0 is a Schwarzschild hole.
+    pub spin: f64,
+}
+
+impl Default for BlackHole {
+    fn default() -> Self {
+        Self { mass: PRESETS[1].mass, spin: 0.0 }
+    }
+}
+
+impl BlackHole {
+    pub fn mass_kg(&self) -> f64 {
+        self.mass * M_SUN
+    }
+
+    /// `r_s = 2GM/c²` in metres, the length unit of the renderer.
+    pub fn schwarzschild_radius(&self) -> f64 {
+        2.0 * G * self.mass_kg() / (C * C)
+    }
+
+    /// `r_s/c` in seconds, the time unit of the renderer.
+    pub fn time_unit(&self) -> f64 {
+        self.schwarzschild_radius() / C
+    }
+
+    /// Convert a length in units of `r_s` to metres.
+    pub fn meters(&self, length: f64) -> f64 {
+        length * self.schwarzschild_radius()
+    }
+
+    /// Convert a time in units of `r_s/c` to seconds.
+    pub fn seconds(&self, time: f64) -> f64 {
+        time * self.time_unit()
+    }
+
+    /// Outer event horizon radius in units of `r_s`.
+    pub fn horizon_radius(&self) -> f64 {
+        Kerr::new(1.0, self.spin).horizon_radius()
+    }
+
+    /// Preset with this mass, if any.
+    pub fn preset(&self) -> Option<&'static Preset> {
+        PRESETS.iter().find(|p| p.mass == self.mass)
+    }
+}
+
+impl fmt::Display for BlackHole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(preset) = self.preset() {
+            write!(f, "{}, ", preset.name)?;
+        }
+        write!(
+            f,
+            "M = {:.3e} M☉, a/M = {:.2}, r_s = {}, r_s/c = {}",
+            self.mass,
+            self.spin,
+            format_length(self.schwarzschild_radius()),
+            format_duration(self.time_unit())
+        )
+    }
+}
+
+/// Mass in solar masses from a number or the key of a preset.
+pub fn parse_mass(s: &str) -> Option<f64> {
+    match PRESETS.iter().find(|p| p.key == s) {
+        Some(preset) => Some(preset.mass),
+        None => s.parse().ok().filter(|m: &f64| m.is_finite() && *m > 0.0),
+    }
+}
+
+/// A length in metres, in whichever of km, AU, ly or pc reads best.
+pub fn format_length(meters: f64) -> String {
+    if meters < 1e9 {
+        format!("{:.1} km", meters / 1e3)
+    } else if meters < 0.1 * LIGHT_YEAR {
+        format!("{:.3} AU", meters / AU)
+    } else if meters < 0.1 * PARSEC {
+        format!("{:.3} ly", meters / LIGHT_YEAR)
+    } else {
+        format!("{:.3} pc", meters / PARSEC)
+    }
+}
+
+/// A duration in seconds, in whichever unit from µs to years reads best.
+pub fn format_duration(seconds: f64) -> String {
+    const UNITS: [(f64, &str); 7] = [
+        (1e-6, "µs"),
+        (1e-3, "ms"),
+        (1.0, "s"),
+        (60.0, "min"),
+        (3600.0, "h"),
+        (86400.0, "d"),
+        (365.25 * 86400.0, "yr"),
+    ];
+    let (scale, unit) = UNITS.iter().rev().find(|(scale, _)| seconds >= *scale).copied().unwrap_or(UNITS[0]);
+    format!("{:.3} {}", seconds / scale, unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn units_scale_with_mass() {
+        let sun = BlackHole { mass: 1.0, spin: 0.0 };
+        assert!((sun.schwarzschild_radius() - 2953.25).abs() < 0.1);
+        assert!((sun.seconds(1.0) - 9.851e-6).abs() < 1e-9);
+
+        let sgr_a = BlackHole::default();
+        assert!((sgr_a.meters(1.0) / (sun.meters(1.0) * 4.297e6) - 1.0).abs() < 1e-12);
+        assert_eq!(sgr_a.preset().unwrap().key, "sgr_a");
+    }
+
+    #[test]
+    fn horizon_shrinks_with_spin() {
+        assert_eq!(BlackHole { mass: 1.0, spin: 0.0 }.horizon_radius(), 1.0);
+        let extreme = BlackHole { mass: 1.0, spin: 0.998 }.horizon_radius();
+        assert!((extreme - 0.5 * (1.0 + (1.0 - 0.998f64.powi(2)).sqrt())).abs() < 1e-12);
+    }
+
+    #[test]
+    fn parses_numbers_and_presets() {
+        assert_eq!(parse_mass("m87"), Some(6.5e9));
+        assert_eq!(parse_mass("1e6"), Some(1e6));
+        assert_eq!(parse_mass("0"), None);
+        assert_eq!(parse_mass("-5"), None);
+        assert_eq!(parse_mass("inf"), None);
+        assert_eq!(parse_mass("sgr"), None);
+    }
+
+    #[test]
+    fn picks_a_readable_unit() {
+        assert_eq!(format_length(2953.25), "3.0 km");
+        assert_eq!(format_length(AU), "1.000 AU");
+        assert_eq!(format_length(0.2 * LIGHT_YEAR), "0.200 ly");
+        assert_eq!(format_length(PARSEC), "1.000 pc");
+        assert_eq!(format_duration(0.5e-3), "500.000 µs");
+        assert_eq!(format_duration(90.0), "1.500 min");
+    }
+}
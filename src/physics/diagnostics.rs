@@ -0,0 +1,149 @@
+use std::fmt;
+
+use nalgebra_glm as glm;
+
+use super::{camera_ray, Spacetime, TraceOptions};
+
+/// Constants of motion of a ray at one point along it.
+#[derive(Debug, Clone, Copy)]
+pub struct Invariants {
+    /// Energy at infinity `E = -p_t`.
+    pub energy: f64,
+    /// Angular momentum: `|x × p|` for Schwarzschild, `L_z` about the spin axis for Kerr.
+    pub angular_momentum: f64,
+    /// Carter constant, only defined for Kerr.
+    pub carter: Option<f64>,
+}
+
+/// How far a ray's invariants moved between its start and end.
+///
+/// Everything is normalised by the initial energy, so values are comparable
+/// between rays: `energy` is relative, `angular_momentum` is in units of
+/// `r_s·E`, `carter` in `r_s²·E²`, and `hamiltonian` is `|H|/E²` at the end of
+/// the ray (it is exactly zero for a null ray).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Drift {
+    pub energy: f64,
+    pub angular_momentum: f64,
+    pub carter: Option<f64>,
+    pub hamiltonian: f64,
+}
+
+impl Drift {
+    pub fn between(start: &Invariants, end: &Invariants, hamiltonian: f64) -> Self {
+        let e0 = start.energy;
+        Self {
+            energy: (end.energy - e0).abs() / e0,
+            angular_momentum: (end.angular_momentum - start.angular_momentum).abs() / e0,
+            carter: start.carter.zip(end.carter).map(|(q0, q)| (q - q0).abs() / (e0 * e0)),
+            hamiltonian: hamiltonian.abs() / (e0 * e0),
+        }
+    }
+}
+
+/// Worst-case and mean drift over a set of rays.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DriftSummary {
+    pub rays: usize,
+    pub max: Drift,
+    sum: Drift,
+}
+
+impl DriftSummary {
+    pub fn add(&mut self, drift: &Drift) {
+        self.rays += 1;
+        self.max.energy = self.max.energy.max(drift.energy);
+        self.max.angular_momentum = self.max.angular_momentum.max(drift.angular_momentum);
+        self.max.hamiltonian = self.max.hamiltonian.max(drift.hamiltonian);
+        self.sum.energy += drift.energy;
+        self.sum.angular_momentum += drift.angular_momentum;
+        self.sum.hamiltonian += drift.hamiltonian;
+        if let Some(q) = drift.carter {
+            self.max.carter = Some(self.max.carter.unwrap_or(0.0).max(q));
+            self.sum.carter = Some(self.sum.carter.unwrap_or(0.0) + q);
+        }
+    }
+
+    pub fn mean(&self) -> Drift {
+        let n = self.rays.max(1) as f64;
+        Drift {
+            energy: self.sum.energy / n,
+            angular_momentum: self.sum.angular_momentum / n,
+            carter: self.sum.carter.map(|q| q / n),
+            hamiltonian: self.sum.hamiltonian / n,
+        }
+    }
+}
+
+impl fmt::Display for DriftSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mean = self.mean();
+        writeln!(f, "Drift over {} rays (mean / max):", self.rays)?;
+        writeln!(f, "  energy           {:.3e} / {:.3e}", mean.energy, self.max.energy)?;
+        writeln!(f, "  angular momentum {:.3e} / {:.3e}", mean.angular_momentum, self.max.angular_momentum)?;
+        if let (Some(mean_q), Some(max_q)) = (mean.carter, self.max.carter) {
+            writeln!(f, "  Carter constant  {:.3e} / {:.3e}", mean_q, max_q)?;
+        }
+        write!(f, "  Hamiltonian      {:.3e} / {:.3e}", mean.hamiltonian, self.max.hamiltonian)
+    }
+}
+
+/// Trace one ray per pixel of a `width`×`height` image as seen from `origin`
+/// and summarise the drift of all of them.
+pub fn survey_image(
+    spacetime: &dyn Spacetime,
+    origin: &glm::DVec3,
+    view: &glm::DMat3,
+    fov: f64,
+    width: u32,
+    height: u32,
+    opts: &TraceOptions,
+) -> DriftSummary {
+    let mut summary = DriftSummary::default();
+    for py in 0..height {
+        for px in 0..width {
+            let dir = camera_ray(view, fov, px as f64 + 0.5, py as f64 + 0.5, width as f64, height as f64);
+            summary.add(&spacetime.trace(origin, &dir, opts).drift);
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::kerr::Kerr;
+
+    #[test]
+    fn drift_is_normalised_by_the_initial_energy() {
+        let start = Invariants { energy: 2.0, angular_momentum: 3.0, carter: Some(10.0) };
+        let end = Invariants { energy: 2.2, angular_momentum: 2.0, carter: Some(12.0) };
+        let drift = Drift::between(&start, &end, -0.4);
+        assert!((drift.energy - 0.1).abs() < 1e-12);
+        assert!((drift.angular_momentum - 0.5).abs() < 1e-12);
+        assert!((drift.carter.unwrap() - 0.5).abs() < 1e-12);
+        assert!((drift.hamiltonian - 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn summary_keeps_worst_and_mean() {
+        let mut summary = DriftSummary::default();
+        for energy in [1.0, 3.0] {
+            summary.add(&Drift { energy, carter: Some(energy), ..Drift::default() });
+        }
+        summary.add(&Drift { energy: 2.0, ..Drift::default() });
+        assert_eq!(summary.rays, 3);
+        assert_eq!(summary.max.energy, 3.0);
+        assert_eq!(summary.mean().energy, 2.0);
+        assert_eq!(summary.max.carter, Some(3.0));
+    }
+
+    #[test]
+    fn survey_covers_every_pixel() {
+        let origin = glm::vec3(0.0, 1.0, -15.0);
+        let view = glm::DMat3::identity();
+        let summary = survey_image(&Kerr::new(1.0, 0.5), &origin, &view, 60.0, 6, 4, &TraceOptions::default());
+        assert_eq!(summary.rays, 24);
+        assert!(summary.max.hamiltonian < 1e-6, "{}", summary);
+    }
+}
@@ -0,0 +1,221 @@
+/// `a + b * factor`, element-wise.
+fn add_scaled<const N: usize>(a: &[f64; N], b: &[f64; N], factor: f64) -> [f64; N] {
+    std::array::from_fn(|i| a[i] + b[i] * factor)
+}
+
+/// `y + h · Σ cᵢ·kᵢ`, element-wise.
+fn combine<const N: usize>(y: &[f64; N], h: f64, terms: &[(f64, &[f64; N])]) -> [f64; N] {
+    std::array::from_fn(|i| y[i] + h * terms.iter().map(|(c, k)| c * k[i]).sum::<f64>())
+}
+
+/// One classic fourth-order Runge–Kutta step of `dy/dλ = f(y)`.
+pub fn rk4_step<const N: usize, F>(f: F, y: &[f64; N], h: f64) -> [f64; N]
+where
+    F: Fn(&[f64; N]) -> [f64; N],
+{
+    let k1 = f(y);
+    let k2 = f(&add_scaled(y, &k1, h / 2.0));
+    let k3 = f(&add_scaled(y, &k2, h / 2.0));
+    let k4 = f(&add_scaled(y, &k3, h));
+
+    std::array::from_fn(|i| y[i] + (h / 6.0) * (k1[i] + 2.0 * k2[i] + 2.0 * k3[i] + k4[i]))
+}
+
+/// How a ray is stepped along its affine parameter.
+#[derive(Debug, Clone, Copy)]
+pub enum Method {
+    /// Fixed-step RK4 using the caller's step length.
+    Rk4,
+    /// Embedded Dormand–Prince 5(4) with per-component error
+    /// `abs_tol + rel_tol·|y|`.
+    Rk45 { abs_tol: f64, rel_tol: f64 },
+}
+
+/// Step counters accumulated over one integration.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StepStats {
+    pub accepted: usize,
+    pub rejected: usize,
+    pub rhs_evals: usize,
+}
+
+// Dormand–Prince 5(4) tableau. Geodesic right-hand sides do not depend on λ,
+// so the nodes `c` are not needed. The 5th-order weights equal the last row
+// of `a`, so the final stage doubles as the first stage of the next step.
+const A21: f64 = 1.0 / 5.0;
+const A31: f64 = 3.0 / 40.0;
+const A32: f64 = 9.0 / 40.0;
+const A41: f64 = 44.0 / 45.0;
+const A42: f64 = -56.0 / 15.0;
+const A43: f64 = 32.0 / 9.0;
+const A51: f64 = 19372.0 / 6561.0;
+const A52: f64 = -25360.0 / 2187.0;
+const A53: f64 = 64448.0 / 6561.0;
+const A54: f64 = -212.0 / 729.0;
+const A61: f64 = 9017.0 / 3168.0;
+const A62: f64 = -355.0 / 33.0;
+const A63: f64 = 46732.0 / 5247.0;
+const A64: f64 = 49.0 / 176.0;
+const A65: f64 = -5103.0 / 18656.0;
+const B1: f64 = 35.0 / 384.0;
+const B3: f64 = 500.0 / 1113.0;
+const B4: f64 = 125.0 / 192.0;
+const B5: f64 = -2187.0 / 6784.0;
+const B6: f64 = 11.0 / 84.0;
+// 5th minus 4th order weights
+const E1: f64 = 71.0 / 57600.0;
+const E3: f64 = -71.0 / 16695.0;
+const E4: f64 = 71.0 / 1920.0;
+const E5: f64 = -17253.0 / 339200.0;
+const E6: f64 = 22.0 / 525.0;
+const E7: f64 = -1.0 / 40.0;
+
+const SAFETY: f64 = 0.9;
+const MIN_SCALE: f64 = 0.2;
+const MAX_SCALE: f64 = 5.0;
+/// Adaptive steps never grow past this multiple of the caller's step length.
+const MAX_STEP_FACTOR: f64 = 10.0;
+const MIN_STEP: f64 = 1e-12;
+
+/// Advances a state with either method and keeps the adaptive step size and
+/// statistics between calls. Works for any right-hand side `dy/dλ = f(y)`.
+pub struct Stepper<const N: usize> {
+    pub method: Method,
+    pub stats: StepStats,
+    /// Step size suggested by the last accepted RK45 step.
+    h: Option<f64>,
+    /// The last accepted state with `f` of it, reused by the next RK45 step
+    /// if it continues from that state (FSAL).
+    k1: Option<([f64; N], [f64; N])>,
+}
+
+impl<const N: usize> Stepper<N> {
+    pub fn new(method: Method) -> Self {
+        Self { method, stats: StepStats::default(), h: None, k1: None }
+    }
+
+    /// Advance `y` by one accepted step. `h_hint` is the step length for RK4;
+    /// RK45 starts from it and caps its adapted steps at a multiple of it.
+    /// Returns `None` if no finite step could be taken, e.g. once the state
+    /// has run into a singularity.
+    pub fn advance<F>(&mut self, f: F, y: &[f64; N], h_hint: f64) -> Option<[f64; N]>
+    where
+        F: Fn(&[f64; N]) -> [f64; N],
+    {
+        match self.method {
+            Method::Rk4 => {
+                self.stats.rhs_evals += 4;
+                let y_new = rk4_step(f, y, h_hint);
+                if !y_new.iter().all(|v| v.is_finite()) {
+                    return None;
+                }
+                self.stats.accepted += 1;
+                Some(y_new)
+            }
+            Method::Rk45 { abs_tol, rel_tol } => self.dopri_step(f, y, h_hint, abs_tol, rel_tol),
+        }
+    }
+
+    fn dopri_step<F>(&mut self, f: F, y: &[f64; N], h_hint: f64, abs_tol: f64, rel_tol: f64) -> Option<[f64; N]>
+    where
+        F: Fn(&[f64; N]) -> [f64; N],
+    {
+        let k1 = match self.k1 {
+            Some((last, k)) if last == *y => k,
+            _ => {
+                self.stats.rhs_evals += 1;
+                f(y)
+            }
+        };
+        let max_step = h_hint.abs() * MAX_STEP_FACTOR;
+        let mut h = self.h.unwrap_or(h_hint).min(max_step);
+
+        loop {
+            let k2 = f(&combine(y, h, &[(A21, &k1)]));
+            let k3 = f(&combine(y, h, &[(A31, &k1), (A32, &k2)]));
+            let k4 = f(&combine(y, h, &[(A41, &k1), (A42, &k2), (A43, &k3)]));
+            let k5 = f(&combine(y, h, &[(A51, &k1), (A52, &k2), (A53, &k3), (A54, &k4)]));
+            let k6 = f(&combine(y, h, &[(A61, &k1), (A62, &k2), (A63, &k3), (A64, &k4), (A65, &k5)]));
+            let y_new = combine(y, h, &[(B1, &k1), (B3, &k3), (B4, &k4), (B5, &k5), (B6, &k6)]);
+            let k7 = f(&y_new);
+            self.stats.rhs_evals += 6;
+
+            let err_vec = combine(&[0.0; N], h, &[(E1, &k1), (E3, &k3), (E4, &k4), (E5, &k5), (E6, &k6), (E7, &k7)]);
+            let err = (err_vec
+                .iter()
+                .enumerate()
+                .map(|(i, e)| {
+                    let scale = abs_tol + rel_tol * y[i].abs().max(y_new[i].abs());
+                    (e / scale).powi(2)
+                })
+                .sum::<f64>()
+                / N as f64)
+                .sqrt();
+
+            if !err.is_finite() && h.abs() <= MIN_STEP {
+                self.h = None;
+                self.k1 = None;
+                return None;
+            }
+            if err <= 1.0 || h.abs() <= MIN_STEP {
+                let scale = if err == 0.0 { MAX_SCALE } else { (SAFETY * err.powf(-0.2)).clamp(MIN_SCALE, MAX_SCALE) };
+                self.h = Some((h * scale).min(max_step));
+                self.k1 = Some((y_new, k7));
+                self.stats.accepted += 1;
+                return Some(y_new);
+            }
+
+            self.stats.rejected += 1;
+            // a non-finite error shrinks the step as far as a large one would
+            let scale = if err.is_finite() { (SAFETY * err.powf(-0.2)).max(MIN_SCALE) } else { MIN_SCALE };
+            h = (h * scale).max(MIN_STEP);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RK45: Method = Method::Rk45 { abs_tol: 1e-10, rel_tol: 1e-10 };
+
+    /// `y = [t, t²/2]`, which Dormand–Prince integrates exactly.
+    fn parabola(y: &[f64; 2]) -> [f64; 2] {
+        [1.0, y[0]]
+    }
+
+    #[test]
+    fn rk45_follows_exponential_decay() {
+        // `[e^-t, t]`
+        let mut stepper = Stepper::new(RK45);
+        let mut y = [1.0, 0.0];
+        for _ in 0..50 {
+            y = stepper.advance(|y| [-y[0], 1.0], &y, 0.1).unwrap();
+        }
+        assert!((y[0] - (-y[1]).exp()).abs() < 1e-9);
+        // one evaluation to start, then six per attempt thanks to FSAL
+        let attempts = stepper.stats.accepted + stepper.stats.rejected;
+        assert_eq!(stepper.stats.rhs_evals, 1 + 6 * attempts);
+    }
+
+    #[test]
+    fn fsal_derivative_is_not_reused_for_another_state() {
+        let mut stepper = Stepper::new(RK45);
+        stepper.advance(parabola, &[0.0, 0.0], 0.1).unwrap();
+
+        let start = [5.0, 12.5];
+        let end = stepper.advance(parabola, &start, 0.1).unwrap();
+        let h = end[0] - start[0];
+        assert!((end[1] - (start[1] + start[0] * h + 0.5 * h * h)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn non_finite_derivative_fails_instead_of_stepping() {
+        let mut stepper = Stepper::new(RK45);
+        assert!(stepper.advance(|_| [f64::NAN; 2], &[1.0, 1.0], 0.1).is_none());
+        assert_eq!(stepper.stats.accepted, 0);
+
+        let mut stepper = Stepper::new(Method::Rk4);
+        assert!(stepper.advance(|y| [1.0 / (y[0] - 1.0)], &[1.0], 0.1).is_none());
+    }
+}
@@ -0,0 +1,80 @@
+pub mod black_hole;
+pub mod diagnostics;
+pub mod integrator;
+pub mod kerr;
+pub mod schwarzschild;
+
+use nalgebra_glm as glm;
+
+use diagnostics::Drift;
+use integrator::{Method, StepStats};
+
+/// Why the integration of a ray stopped.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Termination {
+    /// The ray crossed the event horizon.
+    Horizon,
+    /// The ray moved past the escape radius heading outward.
+    Escaped,
+    /// The step budget ran out before the ray was captured or escaped.
+    MaxSteps,
+    /// The state stopped being finite, so the integrator could not go on.
+    Diverged,
+}
+
+#[derive(Clone, Copy)]
+pub struct TraceOptions {
+    pub method: Method,
+    /// Affine step length as a fraction of the current radius, so steps grow
+    /// far from the hole and shrink near the photon sphere. RK45 uses it as
+    /// the initial step and to bound how far steps may grow.
+    pub step_size: f64,
+    pub max_steps: usize,
+    /// Outgoing rays beyond this radius (or beyond their start radius, if
+    /// larger) are treated as escaped to infinity.
+    pub escape_radius: f64,
+    /// Store every integrated position in `TraceResult::path`.
+    pub record_path: bool,
+}
+
+impl Default for TraceOptions {
+    fn default() -> Self {
+        Self {
+            method: Method::Rk45 { abs_tol: 1e-9, rel_tol: 1e-9 },
+            step_size: 0.01,
+            max_steps: 20_000,
+            escape_radius: 100.0,
+            record_path: false,
+        }
+    }
+}
+
+pub struct TraceResult {
+    pub termination: Termination,
+    pub final_position: glm::DVec3,
+    /// Unit direction of travel at the last integrated point.
+    pub final_direction: glm::DVec3,
+    /// Integrated positions including the origin; empty unless `record_path` is set.
+    pub path: Vec<glm::DVec3>,
+    pub stats: StepStats,
+    /// Change of the constants of motion between the start and end of the ray.
+    pub drift: Drift,
+}
+
+/// A black hole metric whose null geodesics can be traced on the CPU.
+pub trait Spacetime {
+    /// Trace a ray from `origin` along `direction` (same inputs as `traceRay`
+    /// in the shader, in units of `r_s`).
+    fn trace(&self, origin: &glm::DVec3, direction: &glm::DVec3, opts: &TraceOptions) -> TraceResult;
+}
+
+/// World-space direction of the primary ray through fragment coordinate
+/// `(x, y)`, mirroring `main()` in `shaders/blackhole.frag`. `view` holds the
+/// camera right/up/forward vectors as rows, as `Camera::get_view_matrix` does.
+pub fn camera_ray(view: &glm::DMat3, fov: f64, x: f64, y: f64, width: f64, height: f64) -> glm::DVec3 {
+    let u = (x / width * 2.0 - 1.0) * width / height;
+    let v = y / height * 2.0 - 1.0;
+    let tan_half_fov = (fov * 0.5).to_radians().tan();
+    let local = glm::normalize(&glm::vec3(u * tan_half_fov, -v * tan_half_fov, 1.0));
+    glm::normalize(&(view.transpose() * local))
+}
@@ -1,39 +1,112 @@
-use crate::{camera::{Camera, CameraMode, FreeCamDirection}, fps::FpsCounter, renderer::{window::WindowContext, mesh::create_fullscreen_quad, utils::get_uniform}, shader::create_shader_program};
+use crate::{camera::{Camera, CameraMode, FreeCamDirection}, fps::FpsCounter, renderer::{window::WindowContext, mesh::create_fullscreen_quad}, shader::{Define, ShaderProgram, ShaderWatcher}};
 use crate::gl_bindings::*;
 use crate::renderer::skybox::Skybox;
-use crate::renderer::utils::load_texture;
+use crate::renderer::utils::{is_hdr_path, load_texture, save_rgba, save_rgba_f32};
+use crate::renderer::framebuffer::{read_rgba, RenderTarget};
+use crate::cli::{Backend, Options};
+use crate::metadata::{shader_hash, RenderMetadata};
+use crate::scene::Scene;
+use crate::renderer::cpu::CpuRenderer;
+use crate::renderer::frame::{FrameParams, Quality};
+use crate::renderer::blackbody::create_lut_texture;
+use crate::renderer::exposure::{Exposure, ExposureMeter};
+use crate::renderer::post::{Bloom, PostProcess, SHADERS};
+use crate::renderer::poster::{preview_size, report_progress, stitch, tile_grid, DEFAULT_TILE_SIZE};
+use crate::renderer::recorder::{RecordTarget, Recorder};
+use crate::renderer::hud::Hud;
+use crate::renderer::overlay::{load_image, Overlay};
+use crate::renderer::disk::{create_flux_texture, isco_radius, DiskParam, DiskParams, EmissionMode, FluxProfile};
+use crate::physics::{black_hole::{format_duration, format_length, BlackHole, PRESETS}, diagnostics::survey_image, integrator::Method, kerr::Kerr, schwarzschild::Schwarzschild, Spacetime, TraceOptions};
 use glfw::{self,Context, Action, Key};
-use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use chrono::Local;
-use image::{ImageBuffer, Rgba};
+use nalgebra_glm as glm;
 
-const WIDTH: u32 = 1920;
-const HEIGHT: u32 = 1080;
 const TITLE: &str = "Black Hole Renderer";
+/// Largest |a/M| the controls allow; stays clear of a = M, where the horizon degenerates.
+pub const MAX_SPIN: f64 = 0.998;
+/// Exposure change per press of - or =, in EV.
+const EXPOSURE_STEP: f32 = 0.25;
+/// Size of a poster render (the U key) relative to the window.
+const POSTER_SCALE: u32 = 4;
+/// Space between the screenshot and record buttons, in pixels.
+const RECORD_BUTTON_GAP: f32 = 12.0;
+/// Seconds to wait for events between checks while the window is minimized.
+const MINIMIZED_POLL_INTERVAL: f64 = 0.1;
 
 pub struct App {
 	pub window_ctx: WindowContext,
 	pub camera: Camera,
 	pub vao: u32,
 	pub render_disk: bool,
+	/// Doppler beaming and gravitational redshift of the disk emission.
+	pub redshift: bool,
+	pub emission_mode: EmissionMode,
+	pub disk: DiskParams,
+	/// Disk parameter the [ and ] keys adjust; Tab selects the next one.
+	pub disk_param: DiskParam,
 	pub gravitational_lensing: bool,
+	/// Mass and spin; a spin of 0 renders a Schwarzschild hole.
+	pub black_hole: BlackHole,
 	pub fov: f32,
+	pub quality: Quality,
+	pub exposure: Exposure,
+	/// Automatic exposure, metered after every frame while `exposure.auto` is on.
+	pub exposure_meter: ExposureMeter,
+	pub bloom: Bloom,
+	/// HDR scene target, bloom chain and tone mapping pass every frame goes through.
+	pub post: PostProcess,
 	pub passive_tracking: bool,
-	pub shader: u32,
+	pub shader: ShaderProgram,
 	pub fps_counter: FpsCounter,
 	pub skybox: Skybox,
     pub color_map: u32,
+	pub blackbody_lut: u32,
+	/// Refilled from `FrameParams::flux` on every draw.
+	pub flux_lut: u32,
 	pub screenshot_icon: u32,   
+	/// Save the next frame drawn into the window, before the overlay goes
+	/// over it; set by the P key and the screenshot button.
+	pub screenshot_requested: bool,
     pub icon_size: f32,
 	pub is_fullscreen: bool,
     pub windowed_pos: (i32, i32),
     pub windowed_size: (i32, i32),
+	/// Origin of the shader's `u_time`.
+	pub start_time: std::time::Instant,
+	/// Shader time of the last frame drawn into the window.
+	pub frame_time: f32,
+	/// `shader_hash` of the loaded shaders, stored with every render.
+	pub shader_hash: String,
+	pub vertex_shader_path: PathBuf,
+	pub fragment_shader_path: PathBuf,
+	/// Macros injected into the main shader pair.
+	pub shader_defines: Vec<Define>,
+	/// Sources of the main and post-processing shaders, reloaded when they change.
+	pub shader_watcher: ShaderWatcher,
+	/// Compiler log of the last failed reload, shown over the frame until
+	/// a reload succeeds.
+	pub shader_error: Option<String>,
+	/// 2D layer for the buttons and messages over the frame.
+	pub overlay: Overlay,
+	/// Readouts and messages over the frame, shown and hidden with F1.
+	pub hud: Hud,
+	/// Recording in progress, started and stopped with the Y key.
+	pub recorder: Option<Recorder>,
+	/// `--record` or `--record-pipe`; without either, every recording gets
+	/// a new file in recordings/.
+	pub record_target: Option<RecordTarget>,
+	pub record_fps: f64,
+	/// Size of recorded frames; the window size when recording starts if none.
+	pub record_size: Option<(u32, u32)>,
+	/// Asset paths, kept for loading the CPU renderer on demand.
+	pub skybox_dir: PathBuf,
+	pub color_map_path: PathBuf,
 }
 
 impl App {
-	pub fn new() -> Self {
-		let mut window_ctx = WindowContext::new(WIDTH, HEIGHT, TITLE);
+	pub fn new(options: &Options, scene: &Scene) -> Self {
+		let mut window_ctx = WindowContext::new(options.width, options.height, TITLE, options.vsync);
 
 		window_ctx.window.set_key_polling(true);
 		window_ctx.window.set_mouse_button_polling(true);
@@ -41,66 +114,129 @@ impl App {
 		window_ctx.window.set_scroll_polling(true);
 		window_ctx.window.set_framebuffer_size_polling(true);
 
-		let camera = Camera::new();
+		let mut app = Self::with_window(window_ctx, options, scene);
+		if options.fullscreen {
+			app.toggle_fullscreen();
+		}
+		app
+	}
+
+	/// Set up the renderer on an invisible window, for `render_to_file`.
+	pub fn headless(options: &Options, scene: &Scene) -> Result<Self, String> {
+		let window_ctx = WindowContext::hidden(TITLE).map_err(|e| {
+			format!("{}; the gpu backend needs a display, so use --backend cpu or run under xvfb-run", e)
+		})?;
+		Ok(Self::with_window(window_ctx, options, scene))
+	}
+
+	fn with_window(window_ctx: WindowContext, options: &Options, scene: &Scene) -> Self {
+		let camera = scene.camera();
 		let vao = create_fullscreen_quad();
 
-		let skybox = Skybox::load_from_folder("assets/skybox_nebula_dark")
+		let skybox = Skybox::load_from_folder(&options.skybox)
 			.expect("Failed to load skybox");
 
-		let color_map = load_texture("assets/color_map.png")
+		let color_map = load_texture(&options.color_map.to_string_lossy())
 			.expect("Failed to load color map texture");
 
-		let screenshot_icon = load_texture("assets/ss.png")
+		let screenshot_icon = load_image("assets/ss.png")
     		.expect("Failed to load screenshot icon");
 
+		let post = PostProcess::new(vao)
+			.expect("Failed to set up post-processing");
+
+		let overlay = Overlay::new()
+			.expect("Failed to load the overlay renderer");
+
+		// Create shader program but handle compile/link failures gracefully.
+		// Until the sources are fixed and reloaded, the error stays on screen.
+		let mut shader_error = None;
+		let shader = {
+			use std::time::Instant;
+			let start = Instant::now();
+			match ShaderProgram::with_defines(&options.vertex_shader.to_string_lossy(), &options.fragment_shader.to_string_lossy(), &options.defines) {
+				Ok(p) => {
+					let dur = start.elapsed();
+					println!("Loaded main shader in {:.2?}", dur);
+					p
+				}
+				Err(e) => {
+					println!("ERROR: Failed to compile/link main shader: {}", e);
+					shader_error = Some(e.clone());
+					println!("Attempting to load fallback shader to avoid crash...");
+					match ShaderProgram::new("shaders/fallback.vert", "shaders/fallback.frag") {
+						Ok(f) => {
+							let dur = start.elapsed();
+							println!("Loaded fallback shader in {:.2?}", dur);
+							f
+						}
+						Err(e2) => {
+							panic!("Failed to compile both main and fallback shaders. main: {}\nfallback: {}", e, e2);
+						}
+					}
+				}
+			}
+		};
+
 		Self {
 			window_ctx,
 			camera,
 			vao,
-			render_disk: true,
-			gravitational_lensing: true,
-			fov: 60.0,
-			passive_tracking: false,
-			// Create shader program but handle compile/link failures gracefully.
-			shader: {
-				use std::time::Instant;
-				let start = Instant::now();
-				match create_shader_program("shaders/blackhole.vert", "shaders/blackhole.frag") {
-					Ok(p) => {
-						let dur = start.elapsed();
-						println!("Loaded main shader in {:.2?}", dur);
-						p
-					}
-					Err(e) => {
-						println!("ERROR: Failed to compile/link main shader: {}", e);
-						println!("Attempting to load fallback shader to avoid crash...");
-						match create_shader_program("shaders/fallback.vert", "shaders/fallback.frag") {
-							Ok(f) => {
-								let dur = start.elapsed();
-								println!("Loaded fallback shader in {:.2?}", dur);
-								f
-							}
-							Err(e2) => {
-								panic!("Failed to compile both main and fallback shaders. main: {}\nfallback: {}", e, e2);
-							}
-						}
-					}
-				}
+			render_disk: scene.render_disk,
+			redshift: scene.redshift,
+			emission_mode: scene.emission_mode,
+			disk: scene.disk,
+			disk_param: DiskParam::Inner,
+			gravitational_lensing: scene.gravitational_lensing,
+			black_hole: BlackHole {
+				spin: scene.black_hole.spin.clamp(-MAX_SPIN, MAX_SPIN),
+				..scene.black_hole
 			},
+			fov: scene.fov,
+			quality: scene.quality,
+			exposure: scene.exposure,
+			exposure_meter: ExposureMeter::new(),
+			bloom: scene.bloom,
+			post,
+			passive_tracking: false,
+			shader,
 			fps_counter: FpsCounter::new(),
 			color_map,
+			blackbody_lut: create_lut_texture(),
+			flux_lut: create_flux_texture(),
     		skybox,
 			screenshot_icon,
+			screenshot_requested: false,
 			icon_size: 64.0,
 			is_fullscreen: false,
 			windowed_pos: (100, 100),
-			windowed_size: (WIDTH as i32, HEIGHT as i32),
+			windowed_size: (options.width as i32, options.height as i32),
+			start_time: std::time::Instant::now()
+				.checked_sub(std::time::Duration::from_secs_f32(scene.time.max(0.0)))
+				.unwrap_or_else(std::time::Instant::now),
+			frame_time: scene.time,
+			shader_hash: shader_hash(&options.vertex_shader, &options.fragment_shader, &options.defines),
+			vertex_shader_path: options.vertex_shader.clone(),
+			fragment_shader_path: options.fragment_shader.clone(),
+			shader_defines: options.defines.clone(),
+			shader_watcher: ShaderWatcher::new(
+				[options.vertex_shader.clone(), options.fragment_shader.clone()]
+					.into_iter()
+					.chain(SHADERS.iter().map(PathBuf::from)),
+			),
+			shader_error,
+			overlay,
+			hud: Hud::new(),
+			recorder: None,
+			record_target: options.record_target(),
+			record_fps: options.record_fps,
+			record_size: options.record_size,
+			skybox_dir: options.skybox.clone(),
+			color_map_path: options.color_map.clone(),
 		}
 	}
 
 	pub fn run(&mut self) {
-		let start_time = std::time::Instant::now();
-
 		unsafe {
 			let (fb_width, fb_height) = self.window_ctx.window.get_framebuffer_size();
 			Viewport(0, 0, fb_width, fb_height);
@@ -109,11 +245,17 @@ impl App {
 
 		self.manual();
 
+		let mut last_time = self.window_ctx.glfw.get_time();
 		while !self.window_ctx.window.should_close() {
 			let current_time = self.window_ctx.glfw.get_time();
 			self.camera.update(current_time);
+			let dt = (current_time - last_time) as f32;
+			last_time = current_time;
 
 			self.window_ctx.poll();
+			if self.shader_watcher.poll() {
+				self.reload_shaders();
+			}
 
 			let events: Vec<_> = glfw::flush_messages(&self.window_ctx.events).collect();
 			for (_, event) in events {
@@ -128,95 +270,302 @@ impl App {
 				}
 			}
 
+			// a minimized window has no framebuffer to draw into or record
+			let (fb_width, fb_height) = self.window_ctx.window.get_framebuffer_size();
+			if fb_width <= 0 || fb_height <= 0 {
+				self.window_ctx.glfw.wait_events_timeout(MINIMIZED_POLL_INTERVAL);
+				continue;
+			}
+
 			unsafe {
 				Clear(COLOR_BUFFER_BIT | DEPTH_BUFFER_BIT);
 			}
 
-			unsafe {
-            	UseProgram(self.shader);
-        	}
+			let frame = self.frame_params(fb_width as u32, fb_height as u32, self.start_time.elapsed().as_secs_f32());
+			self.frame_time = frame.time;
+			if let Err(e) = self.render_frame(&frame, 0, dt) {
+				self.notify(format!("ERROR: {}", e));
+			}
+			if std::mem::take(&mut self.screenshot_requested) {
+				self.take_screenshot(fb_width as u32, fb_height as u32);
+			}
+			let captured = match &mut self.recorder {
+				Some(recorder) => recorder.capture(fb_width as u32, fb_height as u32),
+				None => Ok(()),
+			};
+			if let Err(e) = captured {
+				self.notify(format!("ERROR: {}", e));
+				self.toggle_recording();
+			}
 
-			let (fb_width, fb_height) = self.window_ctx.window.get_framebuffer_size();
-			let elapsed = start_time.elapsed().as_secs_f32();
-			let cam_pos = self.camera.get_position();
-			let view_mat = self.camera.get_view_matrix();
+			self.draw_controls(fb_height as u32);
+			self.draw_hud(fb_width as u32, fb_height as u32);
+			self.draw_shader_error(fb_width as u32);
+			self.overlay.flush(fb_width as u32, fb_height as u32);
 
-			unsafe {
-				Uniform2f(get_uniform(self.shader, "u_resolution"), fb_width as f32, fb_height as f32);
-				Uniform1f(get_uniform(self.shader, "u_time"), elapsed);
-				Uniform3f(get_uniform(self.shader, "u_camera_pos"), cam_pos.x, cam_pos.y, cam_pos.z);
-				let mat_data = [
-					view_mat.m11, view_mat.m12, view_mat.m13,
-					view_mat.m21, view_mat.m22, view_mat.m23,
-					view_mat.m31, view_mat.m32, view_mat.m33,
-				];
-				UniformMatrix3fv(get_uniform(self.shader, "u_view_matrix"), 1, FALSE, mat_data.as_ptr());
-				Uniform1f(get_uniform(self.shader, "u_fov"), self.fov);
-				Uniform1i(get_uniform(self.shader, "u_render_disk"), if self.render_disk { 1 } else { 0 });
-				Uniform1i(get_uniform(self.shader, "u_gravitational_lensing"), if self.gravitational_lensing { 1 } else { 0 });
-						// Dynamic quality uniforms (defaults -> medium)
-						Uniform1i(get_uniform(self.shader, "u_max_iter"), 600);
-						Uniform1f(get_uniform(self.shader, "u_step_scale"), 1.0);
-						Uniform1i(get_uniform(self.shader, "u_noise_lod"), 2);
-			}
+			self.window_ctx.window.swap_buffers();
+			self.fps_counter.update();
+		}
 
-			unsafe {
-				ActiveTexture(TEXTURE0);
-				BindTexture(TEXTURE_2D, self.color_map);
-				Uniform1i(get_uniform(self.shader, "colorMap"), 0);
+		if self.recorder.is_some() {
+			self.toggle_recording();
+		}
+		unsafe {
+			DeleteVertexArrays(1, &self.vao);
+		}
+	}
 
-				self.skybox.bind(1);
-				Uniform1i(get_uniform(self.shader, "skybox"), 1);
-			}
+	/// Snapshot of the current view, for saving with the S key.
+	pub fn scene(&self) -> Scene {
+		let mut scene = Scene {
+			fov: self.fov,
+			black_hole: self.black_hole,
+			render_disk: self.render_disk,
+			redshift: self.redshift,
+			emission_mode: self.emission_mode,
+			disk: self.disk,
+			gravitational_lensing: self.gravitational_lensing,
+			quality: self.quality,
+			time: self.start_time.elapsed().as_secs_f32(),
+			exposure: self.exposure,
+			bloom: self.bloom,
+			..Scene::default()
+		};
+		scene.set_camera(&self.camera);
+		scene
+	}
 
-			unsafe {
-				BindVertexArray(self.vao);
-				DrawArrays(TRIANGLES, 0, 6);
-				BindVertexArray(0);
-			}
+	/// Switch to the view and settings of `scene`, as if the app had been
+	/// started with it.
+	pub fn set_scene(&mut self, scene: &Scene) {
+		self.camera = scene.camera();
+		self.render_disk = scene.render_disk;
+		self.redshift = scene.redshift;
+		self.emission_mode = scene.emission_mode;
+		self.disk = scene.disk;
+		self.gravitational_lensing = scene.gravitational_lensing;
+		self.black_hole = BlackHole {
+			spin: scene.black_hole.spin.clamp(-MAX_SPIN, MAX_SPIN),
+			..scene.black_hole
+		};
+		self.fov = scene.fov;
+		self.quality = scene.quality;
+		self.exposure = scene.exposure;
+		self.bloom = scene.bloom;
+	}
 
-			unsafe {
-				Disable(DEPTH_TEST);
-				Enable(BLEND);
-				BlendFunc(SRC_ALPHA, ONE_MINUS_SRC_ALPHA);
+	/// Metadata for a render of the current view at shader time `time`.
+	fn metadata(&self, time: f32, backend: Backend) -> RenderMetadata {
+		let mut scene = self.scene();
+		scene.time = time;
+		RenderMetadata::new(scene, self.shader_hash.clone(), backend)
+	}
 
-				MatrixMode(PROJECTION);
-				PushMatrix();
-				LoadIdentity();
-				Ortho(0.0, fb_width as f64, 0.0, fb_height as f64, -1.0, 1.0);
+	fn save_scene(&mut self) {
+		let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S");
+		let filename = format!("scenes/scene_{}.toml", timestamp);
+		match self.scene().save(Path::new(&filename)) {
+			Ok(()) => self.notify(format!("Scene saved to {} (load it with --scene {})", filename, filename)),
+			Err(e) => self.notify(format!("ERROR: {}", e)),
+		}
+	}
 
-				MatrixMode(MODELVIEW);
-				PushMatrix();
-				LoadIdentity();
+	/// Current view and render settings for a `width`×`height` frame at shader time `time`.
+	pub fn frame_params(&self, width: u32, height: u32, time: f32) -> FrameParams {
+		let (max_iter, step_scale, noise_lod) = self.quality.settings();
+		FrameParams {
+			width,
+			height,
+			time,
+			camera_pos: self.camera.get_position(),
+			view_matrix: self.camera.get_view_matrix(),
+			fov: self.fov,
+			render_disk: self.render_disk,
+			redshift: self.redshift,
+			emission_mode: self.emission_mode,
+			gravitational_lensing: self.gravitational_lensing,
+			spin: self.black_hole.spin as f32,
+			rs_meters: self.black_hole.schwarzschild_radius() as f32,
+			max_iter,
+			step_scale,
+			noise_lod,
+			disk: self.disk,
+			flux: FluxProfile::new(self.black_hole.spin, self.disk.outer as f64),
+			exposure: self.exposure,
+			bloom: self.bloom,
+		}
+	}
 
-				BindTexture(TEXTURE_2D, self.screenshot_icon);
+	/// Draw the black hole as linear radiance over the whole viewport of the
+	/// bound framebuffer. For a tiled render the viewport is one tile of the
+	/// frame, whose bottom-left pixel is at `tile_offset`.
+	fn draw_scene(&self, frame: &FrameParams, tile_offset: (u32, u32)) {
+		let shader = &self.shader;
+		shader.bind();
 
-				let size = self.icon_size;
-				let x = 20.0;
-				let y = 20.0;
+		shader.set_vec2("u_resolution", glm::vec2(frame.width as f32, frame.height as f32));
+		shader.set_vec2("u_tile_offset", glm::vec2(tile_offset.0 as f32, tile_offset.1 as f32));
+		shader.set_f32("u_time", frame.time);
+		shader.set_vec3("u_camera_pos", &frame.camera_pos);
+		// the shader takes right/up/forward as columns
+		shader.set_mat3("u_view_matrix", &frame.view_matrix.transpose());
+		shader.set_f32("u_fov", frame.fov);
+		shader.set_bool("u_render_disk", frame.render_disk);
+		shader.set_bool("u_redshift", frame.redshift);
+		shader.set_i32("u_emission_mode", frame.emission_mode.index());
+		shader.set_bool("u_gravitational_lensing", frame.gravitational_lensing);
+		shader.set_f32("u_spin", frame.spin);
+		shader.set_i32("u_max_iter", frame.max_iter);
+		shader.set_f32("u_step_scale", frame.step_scale);
+		shader.set_i32("u_noise_lod", frame.noise_lod);
 
-				Begin(QUADS);
-				TexCoord2f(0.0, 0.0); Vertex2f(x, y);
-				TexCoord2f(1.0, 0.0); Vertex2f(x + size, y);
-				TexCoord2f(1.0, 1.0); Vertex2f(x + size, y + size);
-				TexCoord2f(0.0, 1.0); Vertex2f(x, y + size);
-				End();
+		let disk = &frame.disk;
+		shader.set_f32("u_disk_inner", disk.inner_radius(frame.spin as f64) as f32);
+		shader.set_f32("u_disk_outer", disk.outer);
+		shader.set_f32("u_disk_height", disk.height);
+		shader.set_f32("u_disk_density_v", disk.density_v);
+		shader.set_f32("u_disk_density_h", disk.density_h);
+		shader.set_f32("u_disk_noise_scale", disk.noise_scale);
+		shader.set_f32("u_disk_speed", disk.speed);
+		shader.set_f32("u_disk_density_scale", disk.density_scale);
+		shader.set_f32("u_disk_absorption", disk.absorption);
+		let (flux_start, flux_end) = frame.flux.range;
+		shader.set_vec2("u_disk_flux_range", glm::vec2(flux_start as f32, flux_end as f32));
+		let t_star = disk.temperature_scale(frame.rs_meters as f64);
+		shader.set_f32("u_disk_peak_temperature", frame.flux.peak_temperature(t_star) as f32);
 
-				PopMatrix();
-				MatrixMode(PROJECTION);
-				PopMatrix();
-				MatrixMode(MODELVIEW);
+		unsafe {
+			ActiveTexture(TEXTURE0);
+			BindTexture(TEXTURE_2D, self.color_map);
+		}
+		shader.set_sampler("colorMap", 0);
 
-				Enable(DEPTH_TEST);
-				Disable(BLEND);
-			}
+		self.skybox.bind(1);
+		shader.set_sampler("skybox", 1);
 
-			self.window_ctx.window.swap_buffers();
-			self.fps_counter.update();
+		unsafe {
+			ActiveTexture(TEXTURE2);
+			BindTexture(TEXTURE_2D, self.blackbody_lut);
 		}
+		shader.set_sampler("blackbodyLut", 2);
 
 		unsafe {
-			DeleteVertexArrays(1, &self.vao);
+			ActiveTexture(TEXTURE3);
+		}
+		frame.flux.upload(self.flux_lut);
+		shader.set_sampler("diskFluxLut", 3);
+
+		unsafe {
+			BindVertexArray(self.vao);
+			DrawArrays(TRIANGLES, 0, 6);
+			BindVertexArray(0);
+		}
+	}
+
+	/// Exposure in EV the frame is drawn with, including the metered one.
+	fn exposure_ev(&self, frame: &FrameParams) -> f32 {
+		if frame.exposure.auto {
+			frame.exposure.ev + self.exposure_meter.ev
+		} else {
+			frame.exposure.ev
+		}
+	}
+
+	/// Draw `frame` into the HDR scene target, meter it when auto exposure is
+	/// on, and bloom and tone map it into framebuffer `output`. The meter
+	/// adapts over `dt` seconds, a frame late; an infinite `dt` meters this
+	/// frame and jumps straight to its exposure.
+	fn render_frame(&mut self, frame: &FrameParams, output: u32, dt: f32) -> Result<(), String> {
+		self.post.resize(frame.width, frame.height)?;
+		self.post.scene().bind();
+		self.draw_scene(frame, (0, 0));
+		if frame.exposure.auto {
+			if dt.is_finite() {
+				self.exposure_meter.update(self.post.scene(), dt)?;
+			} else {
+				self.exposure_meter.meter(self.post.scene())?;
+			}
+		}
+		let ev = self.exposure_ev(frame);
+		self.post.apply(frame, ev, output);
+		Ok(())
+	}
+
+	/// Render a single frame offscreen at `width`×`height` and save it to `path`.
+	/// An `.exr` or `.hdr` path gets the linear radiance of the scene, before
+	/// bloom and tone mapping. Frames larger than `tile_size` on either side
+	/// are rendered in tiles.
+	pub fn render_to_file(&mut self, width: u32, height: u32, time: f32, tile_size: u32, path: &Path) -> Result<(), String> {
+		let frame = self.frame_params(width, height, time);
+		if width > tile_size || height > tile_size {
+			return self.render_poster(&frame, tile_size, path);
+		}
+
+		let pixels = self.render_rgba(&frame)?;
+		if is_hdr_path(path) {
+			return save_rgba_f32(path, width, height, self.post.scene().read_pixels_f32());
+		}
+		save_rgba(path, width, height, pixels, &self.metadata(time, Backend::Gpu))
+	}
+
+	/// Render `frame` offscreen in one piece and return it as top-to-bottom
+	/// RGBA rows. The linear radiance stays in `post.scene()`.
+	pub fn render_rgba(&mut self, frame: &FrameParams) -> Result<Vec<u8>, String> {
+		let target = RenderTarget::new(frame.width, frame.height)?;
+		// no previous frame to adapt from, so the meter jumps straight to this one
+		self.render_frame(frame, target.fbo, f32::INFINITY)?;
+		Ok(target.read_pixels())
+	}
+
+	/// Render `frame` in tiles of at most `tile_size` pixels, stitch them on
+	/// the CPU and save the image to `path`, for sizes beyond what the GPU
+	/// draws in one piece. Exposure and bloom come from a preview of the
+	/// whole frame, so the tiles match at their seams.
+	fn render_poster(&mut self, frame: &FrameParams, tile_size: u32, path: &Path) -> Result<(), String> {
+		let (width, height) = (frame.width, frame.height);
+		let hdr = is_hdr_path(path);
+		if !hdr {
+			let (preview_width, preview_height) = preview_size(width, height);
+			let preview = FrameParams { width: preview_width, height: preview_height, ..*frame };
+			self.post.resize(preview_width, preview_height)?;
+			self.post.scene().bind();
+			self.draw_scene(&preview, (0, 0));
+			if frame.exposure.auto {
+				self.exposure_meter.meter(self.post.scene())?;
+			}
+			if frame.bloom.enabled {
+				self.post.bloom(&frame.bloom);
+			}
+		}
+		let ev = self.exposure_ev(frame);
+
+		let len = width as usize * height as usize * 4;
+		let mut radiance = if hdr { vec![0.0f32; len] } else { Vec::new() };
+		let mut pixels = if hdr { Vec::new() } else { vec![0u8; len] };
+		let tiles = tile_grid(width, height, tile_size);
+		// float scene and tone mapped output of one tile, replaced when the size changes at the edges
+		let mut targets: Option<(RenderTarget, RenderTarget)> = None;
+		let start = std::time::Instant::now();
+		for (i, tile) in tiles.iter().enumerate() {
+			if targets.as_ref().is_none_or(|(scene, _)| (scene.width, scene.height) != (tile.width, tile.height)) {
+				targets = Some((RenderTarget::hdr(tile.width, tile.height)?, RenderTarget::new(tile.width, tile.height)?));
+			}
+			let (scene, output) = targets.as_ref().unwrap();
+			scene.bind();
+			self.draw_scene(frame, tile.gl_offset(height));
+			if hdr {
+				stitch(&mut radiance, width, tile, &scene.read_pixels_f32());
+			} else {
+				self.post.composite(scene, frame, ev, output.fbo, tile.texture_rect(width, height));
+				stitch(&mut pixels, width, tile, &output.read_pixels());
+			}
+			report_progress("tile", i + 1, tiles.len(), start);
+		}
+
+		if hdr {
+			save_rgba_f32(path, width, height, radiance)
+		} else {
+			save_rgba(path, width, height, pixels, &self.metadata(frame.time, Backend::Gpu))
 		}
 	}
 
@@ -231,7 +580,7 @@ impl App {
 				None,
 			);
 			self.is_fullscreen = false;
-			println!("Switched to windowed mode");
+			self.notify("Switched to windowed mode".to_string());
 		} else {
 			self.windowed_pos = self.window_ctx.window.get_pos();
 			let (w, h) = self.window_ctx.window.get_size();
@@ -249,12 +598,14 @@ impl App {
 							Some(mode.refresh_rate),
 						);
 						self.is_fullscreen = true;
-						println!("Switched to fullscreen mode");
 					}
 				} else {
 					println!("No primary monitor found!");
 				}
 			});
+			if self.is_fullscreen {
+				self.notify("Switched to fullscreen mode".to_string());
+			}
 		}
 	}
 
@@ -262,7 +613,7 @@ impl App {
 		match event {
 			glfw::WindowEvent::Key(Key::T, _, Action::Press, _) => {
 				self.passive_tracking = !self.passive_tracking;
-				println!("Passive mouse tracking: {}", if self.passive_tracking { "ON" } else { "OFF" });
+				self.notify(format!("Passive mouse tracking: {}", if self.passive_tracking { "ON" } else { "OFF" }));
 			}
 			glfw::WindowEvent::FramebufferSize(width, height) => {
 				unsafe {
@@ -273,40 +624,118 @@ impl App {
 			| glfw::WindowEvent::Key(Key::F12, _, Action::Press, _) => {
 				self.toggle_fullscreen();
 			}
+			glfw::WindowEvent::Key(Key::F1, _, Action::Press, _) => {
+				self.hud.visible = !self.hud.visible;
+			}
 			glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
 				self.window_ctx.window.set_should_close(true);
 			}
 			glfw::WindowEvent::Key(Key::D, _, Action::Press, _) => {
 				self.render_disk = !self.render_disk;
-				println!("Accretion disk: {}", if self.render_disk { "ON" } else { "OFF" });
+				self.notify(format!("Accretion disk: {}", if self.render_disk { "ON" } else { "OFF" }));
+			}
+			glfw::WindowEvent::Key(Key::X, _, Action::Press, _) => {
+				self.redshift = !self.redshift;
+				self.notify(format!("Doppler beaming and redshift: {}", if self.redshift { "ON" } else { "OFF" }));
+			}
+			glfw::WindowEvent::Key(Key::O, _, Action::Press, _) => {
+				self.emission_mode = self.emission_mode.next();
+				self.notify(format!("Disk emission: {}", self.emission_mode.name()));
+				if self.emission_mode == EmissionMode::Blackbody {
+					self.print_disk_temperature();
+				}
+			}
+			glfw::WindowEvent::Key(Key::J, _, Action::Press, _) => {
+				self.disk.isco = !self.disk.isco;
+				self.notify(format!(
+					"Disk inner edge: {:.3} r_s ({})",
+					self.disk.inner_radius(self.black_hole.spin),
+					if self.disk.isco { "ISCO" } else { "disk parameter inner" }
+				));
+			}
+			glfw::WindowEvent::Key(Key::H, _, Action::Press, _) => {
+				self.exposure.tonemap = self.exposure.tonemap.next();
+				self.notify(format!("Tone mapping: {}", self.exposure.tonemap.name()));
+			}
+			glfw::WindowEvent::Key(Key::Minus, _, Action::Press | Action::Repeat, _) => {
+				self.adjust_exposure(-EXPOSURE_STEP);
+			}
+			glfw::WindowEvent::Key(Key::Equal, _, Action::Press | Action::Repeat, _) => {
+				self.adjust_exposure(EXPOSURE_STEP);
+			}
+			glfw::WindowEvent::Key(Key::A, _, Action::Press, _) => {
+				self.exposure.auto = !self.exposure.auto;
+				self.notify(format!("Auto exposure: {}", if self.exposure.auto { "ON" } else { "OFF" }));
+			}
+			glfw::WindowEvent::Key(Key::Z, _, Action::Press, _) => {
+				self.bloom.enabled = !self.bloom.enabled;
+				self.notify(format!("Bloom: {}", if self.bloom.enabled { "ON" } else { "OFF" }));
+			}
+			glfw::WindowEvent::Key(Key::Comma, _, Action::Press | Action::Repeat, _) => {
+				self.adjust_bloom(-1.0);
+			}
+			glfw::WindowEvent::Key(Key::Period, _, Action::Press | Action::Repeat, _) => {
+				self.adjust_bloom(1.0);
 			}
 			glfw::WindowEvent::Key(Key::G, _, Action::Press, _) => {
 				self.gravitational_lensing = !self.gravitational_lensing;
-				println!("Gravitational lensing: {}", if self.gravitational_lensing { "ON" } else { "OFF" });
+				self.notify(format!("Gravitational lensing: {}", if self.gravitational_lensing { "ON" } else { "OFF" }));
+			}
+			glfw::WindowEvent::Key(Key::K, _, Action::Press | Action::Repeat, _) => {
+				self.adjust_spin(-0.05);
+			}
+			glfw::WindowEvent::Key(Key::L, _, Action::Press | Action::Repeat, _) => {
+				self.adjust_spin(0.05);
+			}
+			glfw::WindowEvent::Key(Key::M, _, Action::Press, _) => {
+				self.next_mass_preset();
+			}
+			glfw::WindowEvent::Key(Key::I, _, Action::Press, _) => {
+				self.print_black_hole();
+			}
+			glfw::WindowEvent::Key(Key::Tab, _, Action::Press, _) => {
+				self.disk_param = self.disk_param.next();
+				self.print_disk_param();
+			}
+			glfw::WindowEvent::Key(Key::LeftBracket, _, Action::Press | Action::Repeat, _) => {
+				self.disk.adjust(self.disk_param, -1.0);
+				self.print_disk_param();
+			}
+			glfw::WindowEvent::Key(Key::RightBracket, _, Action::Press | Action::Repeat, _) => {
+				self.disk.adjust(self.disk_param, 1.0);
+				self.print_disk_param();
 			}
 			glfw::WindowEvent::Key(Key::Num1, _, Action::Press, _) => {
-				self.camera.set_mode(CameraMode::FreeOrbit);
+				let message = self.camera.set_mode(CameraMode::FreeOrbit);
+				self.notify(message);
 			}
 			glfw::WindowEvent::Key(Key::Num2, _, Action::Press, _) => {
-				self.camera.set_mode(CameraMode::AutoOrbit);
+				let message = self.camera.set_mode(CameraMode::AutoOrbit);
+				self.notify(message);
 			}
 			glfw::WindowEvent::Key(Key::Num3, _, Action::Press, _) => {
-				self.camera.set_mode(CameraMode::FrontView);
+				let message = self.camera.set_mode(CameraMode::FrontView);
+				self.notify(message);
 			}
 			glfw::WindowEvent::Key(Key::Num4, _, Action::Press, _) => {
-				self.camera.set_mode(CameraMode::TopView);
+				let message = self.camera.set_mode(CameraMode::TopView);
+				self.notify(message);
 			}
 			glfw::WindowEvent::Key(Key::Q, _, Action::Press, _) => {
-				self.camera.adjust_roll(-0.1);
+				let message = self.camera.adjust_roll(-0.1);
+				self.notify(message);
 			}
 			glfw::WindowEvent::Key(Key::E, _, Action::Press, _) => {
-				self.camera.adjust_roll(0.1);
+				let message = self.camera.adjust_roll(0.1);
+				self.notify(message);
 			}
 			glfw::WindowEvent::Key(Key::R, _, Action::Press, _) => {
-				self.camera.reset_roll();
+				let message = self.camera.reset_roll();
+				self.notify(message);
 			}
 			glfw::WindowEvent::Key(Key::C, _, Action::Press, _) => {
-				self.camera.toggle_camera_type();
+				let message = self.camera.toggle_camera_type();
+				self.notify(message);
 				// Reset mouse deltas to avoid a large jump when switching camera types.
 				let (x, y) = self.window_ctx.window.get_cursor_pos();
 				self.camera.last_x = x;
@@ -332,8 +761,32 @@ impl App {
 					self.camera.move_freecam(FreeCamDirection::Right);
 				}
 			}
+			glfw::WindowEvent::Key(Key::P, _, Action::Press, mods) if mods.contains(glfw::Modifiers::Shift) => {
+				self.take_hdr_screenshot();
+			}
 			glfw::WindowEvent::Key(Key::P, _, Action::Press, _) => {
-				self.take_screenshot();
+				self.screenshot_requested = true;
+			}
+			glfw::WindowEvent::Key(Key::W, _, Action::Press, _) => {
+				self.reload_shaders();
+			}
+			glfw::WindowEvent::Key(Key::Y, _, Action::Press, _) => {
+				self.toggle_recording();
+			}
+			glfw::WindowEvent::Key(Key::V, _, Action::Press, _) => {
+				self.trace_reference_ray();
+			}
+			glfw::WindowEvent::Key(Key::S, _, Action::Press, _) => {
+				self.save_scene();
+			}
+			glfw::WindowEvent::Key(Key::N, _, Action::Press, _) => {
+				self.take_cpu_screenshot();
+			}
+			glfw::WindowEvent::Key(Key::U, _, Action::Press, _) => {
+				self.take_poster_screenshot();
+			}
+			glfw::WindowEvent::Key(Key::B, _, Action::Press, _) => {
+				self.survey_drift();
 			}
 			glfw::WindowEvent::MouseButton(glfw::MouseButton::Button1, Action::Press, _) => {
 				let (x, y) = self.window_ctx.window.get_cursor_pos();
@@ -345,9 +798,12 @@ impl App {
 				let icon_y = 20.0;
 				let icon_size = self.icon_size as f64;
 
+				let record_x = icon_x + icon_size + RECORD_BUTTON_GAP as f64;
+
 				if x >= icon_x && x <= icon_x + icon_size && y >= icon_y && y <= icon_y + icon_size {
-					println!("Screenshot button clicked!");
-					self.take_screenshot();
+					self.screenshot_requested = true;
+				} else if x >= record_x && x <= record_x + icon_size && y >= icon_y && y <= icon_y + icon_size {
+					self.toggle_recording();
 				} else {
 					self.camera.dragging = true;
 					let (x, y) = self.window_ctx.window.get_cursor_pos();
@@ -372,44 +828,373 @@ impl App {
 		}
 	}
 
-	fn take_screenshot(&self) {
-		unsafe {
-			let (width, height) = self.window_ctx.window.get_framebuffer_size();
-			let mut pixels = vec![0u8; (width * height * 4) as usize];
-			ReadPixels(
-				0,
-				0,
-				width,
-				height,
-				RGBA,
-				UNSIGNED_BYTE,
-				pixels.as_mut_ptr() as *mut std::ffi::c_void,
-			);
+	/// Save the `width`×`height` frame just drawn into the window as a PNG
+	/// with the view embedded, plus a scene file of the same name. Called
+	/// before the overlay is drawn, so the image shows only the render.
+	fn take_screenshot(&mut self, width: u32, height: u32) {
+		let pixels = read_rgba(width, height);
 
-			let mut flipped = vec![0u8; pixels.len()];
-			for y in 0..height {
-				let src = (y * width * 4) as usize;
-				let dst = ((height - 1 - y) * width * 4) as usize;
-				flipped[dst..dst + (width * 4) as usize]
-					.copy_from_slice(&pixels[src..src + (width * 4) as usize]);
+		let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S");
+		let filename = format!("screenshots/screenshot_{}.png", timestamp);
+		let metadata = self.metadata(self.frame_time, Backend::Gpu);
+		match save_rgba(Path::new(&filename), width, height, pixels, &metadata) {
+			Ok(()) => {
+				self.notify(format!("Screenshot saved to {}", filename));
+				save_sidecar(&metadata, Path::new(&filename));
 			}
+			Err(e) => self.notify(format!("ERROR: {}", e)),
+		}
+	}
+
+	/// Compile the main and post-processing shaders again. Programs that fail
+	/// leave the last working ones in use, and their log on screen.
+	fn reload_shaders(&mut self) {
+		let start = std::time::Instant::now();
+		let mut errors = Vec::new();
+		match ShaderProgram::with_defines(
+			&self.vertex_shader_path.to_string_lossy(),
+			&self.fragment_shader_path.to_string_lossy(),
+			&self.shader_defines,
+		) {
+			Ok(program) => self.shader = program,
+			Err(e) => errors.push(e),
+		}
+		if let Err(e) = self.post.reload() {
+			errors.push(e);
+		}
+
+		if errors.is_empty() {
+			self.shader_hash = shader_hash(&self.vertex_shader_path, &self.fragment_shader_path, &self.shader_defines);
+			self.shader_error = None;
+			self.notify(format!("Reloaded shaders in {:.2?}", start.elapsed()));
+		} else {
+			let log = errors.join("\n");
+			println!("ERROR: Shader reload failed, keeping the last working shaders:\n{}", log);
+			self.shader_error = Some(log);
+		}
+	}
+
+	/// Queue the screenshot button in the bottom left corner of the window
+	/// and the record button next to it, solid red while recording.
+	fn draw_controls(&mut self, height: u32) {
+		let size = self.icon_size;
+		let (x, y) = (20.0, height as f32 - 20.0 - size);
+		self.overlay.image(self.screenshot_icon, x, y, size, size, [1.0; 4]);
+		let alpha = if self.recorder.is_some() { 1.0 } else { 0.5 };
+		self.overlay.circle(x + size + RECORD_BUTTON_GAP + size / 2.0, y + size / 2.0, size * 0.4, [0.9, 0.1, 0.1, alpha]);
+	}
+
+	/// Queue the HUD for a `width`×`height` window.
+	fn draw_hud(&mut self, width: u32, height: u32) {
+		let readout = self.hud_readout();
+		self.hud.draw(&mut self.overlay, &readout, &self.fps_counter, width, height);
+	}
 
-			let dir = Path::new("screenshots");
-			if !dir.exists() {
-				fs::create_dir_all(dir).expect("Failed to create screenshots directory");
+	/// State of the view for the HUD, one setting per line.
+	fn hud_readout(&self) -> String {
+		let bh = &self.black_hole;
+		let position = self.camera.get_position();
+		let distance = glm::length(&position);
+		let on = |flag: bool| if flag { "on" } else { "off" };
+		let mass = match bh.preset() {
+			Some(preset) => format!("{:.3e} Msun ({})", bh.mass, preset.key),
+			None => format!("{:.3e} Msun", bh.mass),
+		};
+		let exposure = if self.exposure.auto {
+			format!("{:+.2} EV auto ({:+.2} metered)", self.exposure.ev, self.exposure_meter.ev)
+		} else {
+			format!("{:+.2} EV", self.exposure.ev)
+		};
+		[
+			format!("{:.1} fps  {:.2} ms", self.fps_counter.fps, 1000.0 / self.fps_counter.fps.max(1e-3)),
+			format!("distance  {:.2} r_s = {}", distance, format_length(bh.meters(distance as f64))),
+			format!(
+				"elevation {:.1} deg  azimuth {:.1} deg",
+				(position.y / distance).clamp(-1.0, 1.0).acos().to_degrees(),
+				position.z.atan2(position.x).to_degrees()
+			),
+			format!("mass      {}", mass),
+			format!("spin      a/M = {:.2}", bh.spin),
+			format!("quality   {}", self.quality.name()),
+			format!("disk {}  lensing {}  redshift {}", on(self.render_disk), on(self.gravitational_lensing), on(self.redshift)),
+			format!("emission  {}", self.emission_mode.name()),
+			format!("tonemap   {}  bloom {}", self.exposure.tonemap.name(), on(self.bloom.enabled)),
+			format!("exposure  {}", exposure),
+		]
+		.join("\n")
+	}
+
+	/// Report a change of state in the terminal and, for a few seconds, on
+	/// the HUD.
+	fn notify(&mut self, message: String) {
+		println!("{}", message);
+		self.hud.message(message);
+	}
+
+	/// Queue the log of a failed shader build over the top of the window.
+	fn draw_shader_error(&mut self, width: u32) {
+		let Some(error) = &self.shader_error else {
+			return;
+		};
+		let title = "Shader error - drawing with the last working shaders until it is fixed";
+		let text = format!("{}\n{}", title, error);
+		let (_, text_height) = self.overlay.measure(&text);
+		let margin = self.overlay.cell_width;
+		self.overlay.rect(0.0, 0.0, width as f32, text_height + 2.0 * margin, [0.0, 0.0, 0.0, 0.75]);
+		self.overlay.text(title, margin, margin, [1.0, 0.35, 0.3, 1.0]);
+		self.overlay.text(error, margin, margin + self.overlay.cell_height, [1.0, 1.0, 1.0, 1.0]);
+	}
+
+	/// Start recording the window to `record_target` as a YUV4MPEG2 stream,
+	/// or stop the recording in progress.
+	fn toggle_recording(&mut self) {
+		if let Some(recorder) = self.recorder.take() {
+			let target = recorder.target.clone();
+			match recorder.finish() {
+				Ok(frames) => self.notify(format!("Recorded {} frames to {}", frames, target.describe())),
+				Err(e) => self.notify(format!("ERROR: {}", e)),
 			}
+			return;
+		}
 
+		let (fb_width, fb_height) = self.window_ctx.window.get_framebuffer_size();
+		let (width, height) = self.record_size.unwrap_or((fb_width as u32, fb_height as u32));
+		let target = self.record_target.clone().unwrap_or_else(|| {
 			let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S");
-			let filename = format!("screenshots/screenshot_{}.png", timestamp);
+			RecordTarget::File(PathBuf::from(format!("recordings/recording_{}.y4m", timestamp)))
+		});
+		match Recorder::start(target, width, height, self.record_fps) {
+			Ok(recorder) => {
+				self.notify(format!("Recording {}x{} at {} fps to {} (Y to stop)", width, height, self.record_fps, recorder.target.describe()));
+				self.recorder = Some(recorder);
+			}
+			Err(e) => self.notify(format!("ERROR: {}", e)),
+		}
+	}
+
+	/// Save the linear radiance of the last frame, before bloom and tone
+	/// mapping, as OpenEXR.
+	fn take_hdr_screenshot(&mut self) {
+		let scene = self.post.scene();
+		let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S");
+		let filename = format!("screenshots/screenshot_{}.exr", timestamp);
+		match save_rgba_f32(Path::new(&filename), scene.width, scene.height, scene.read_pixels_f32()) {
+			Ok(()) => {
+				self.notify(format!("HDR screenshot saved to {}", filename));
+				save_sidecar(&self.metadata(self.frame_time, Backend::Gpu), Path::new(&filename));
+			}
+			Err(e) => self.notify(format!("ERROR: {}", e)),
+		}
+	}
+
+	/// Render the current view at `POSTER_SCALE` times the window size in
+	/// tiles and save it next to the regular screenshots.
+	fn take_poster_screenshot(&mut self) {
+		let (width, height) = self.window_ctx.window.get_framebuffer_size();
+		let (width, height) = (width as u32 * POSTER_SCALE, height as u32 * POSTER_SCALE);
+		let frame = self.frame_params(width, height, self.start_time.elapsed().as_secs_f32());
+		println!("Rendering a {}x{} poster...", width, height);
+
+		let start = std::time::Instant::now();
+		let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S");
+		let filename = format!("screenshots/poster_{}.png", timestamp);
+		match self.render_poster(&frame, DEFAULT_TILE_SIZE, Path::new(&filename)) {
+			Ok(()) => {
+				self.notify(format!("Poster saved to {} in {:.2?}", filename, start.elapsed()));
+				save_sidecar(&self.metadata(frame.time, Backend::Gpu), Path::new(&filename));
+			}
+			Err(e) => self.notify(format!("ERROR: {}", e)),
+		}
+	}
+
+	/// Render the current view with the CPU renderer and save it next to the
+	/// regular screenshots, for comparison with the shader.
+	fn take_cpu_screenshot(&mut self) {
+		let (width, height) = self.window_ctx.window.get_framebuffer_size();
+		let frame = self.frame_params(width as u32, height as u32, self.start_time.elapsed().as_secs_f32());
+
+		let start = std::time::Instant::now();
+		let image = match CpuRenderer::load(&self.skybox_dir, &self.color_map_path, &self.shader_defines) {
+			Ok(cpu) => cpu.render(&frame),
+			Err(e) => {
+				self.notify(format!("ERROR: {}", e));
+				return;
+			}
+		};
+
+		let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S");
+		let filename = format!("screenshots/cpu_render_{}.png", timestamp);
+		let metadata = self.metadata(frame.time, Backend::Cpu);
+		match save_rgba(Path::new(&filename), frame.width, frame.height, image.into_raw(), &metadata) {
+			Ok(()) => {
+				self.notify(format!("CPU render saved to {} in {:.2?}", filename, start.elapsed()));
+				save_sidecar(&metadata, Path::new(&filename));
+			}
+			Err(e) => self.notify(format!("ERROR: {}", e)),
+		}
+	}
+
+	fn adjust_exposure(&mut self, delta: f32) {
+		self.exposure.ev += delta;
+		if self.exposure.auto {
+			self.notify(format!(
+				"Exposure compensation: {:+.2} EV (metered {:+.2} EV)",
+				self.exposure.ev, self.exposure_meter.ev
+			));
+		} else {
+			self.notify(format!("Exposure: {:+.2} EV", self.exposure.ev));
+		}
+	}
+
+	/// Move the bloom intensity `steps` steps of `Bloom::INTENSITY_RANGE`.
+	fn adjust_bloom(&mut self, steps: f32) {
+		let (min, max, step) = Bloom::INTENSITY_RANGE;
+		self.bloom.intensity = (self.bloom.intensity + steps * step).clamp(min, max);
+		self.notify(format!("Bloom intensity: {:.2}", self.bloom.intensity));
+	}
+
+	fn adjust_spin(&mut self, delta: f64) {
+		let spin = &mut self.black_hole.spin;
+		*spin = (*spin + delta).clamp(-MAX_SPIN, MAX_SPIN);
+		if spin.abs() < 1e-3 {
+			*spin = 0.0;
+		}
+		let message = format!("Black hole spin a/M: {:.2}", spin);
+		self.notify(message);
+		self.print_isco();
+	}
 
-			let img = ImageBuffer::<Rgba<u8>, _>::from_raw(width as u32, height as u32, flipped)
-				.expect("Failed to create ImageBuffer");
-			img.save(&filename).expect("Failed to save screenshot");
+	/// Switch to the next mass preset; the picture is unchanged, only the
+	/// physical scale of every length and time.
+	fn next_mass_preset(&mut self) {
+		let next = PRESETS
+			.iter()
+			.position(|p| p.mass == self.black_hole.mass)
+			.map_or(0, |i| (i + 1) % PRESETS.len());
+		self.black_hole.mass = PRESETS[next].mass;
+		self.print_black_hole();
+	}
+
+	/// Mass, spin and the current view in physical units.
+	fn print_black_hole(&self) {
+		let bh = &self.black_hole;
+		let distance = glm::length(&self.camera.get_position()) as f64;
+		println!("Black hole: {}", bh);
+		println!(
+			"  horizon r+ = {:.3} r_s = {}, camera at {:.2} r_s = {} ({} light travel time)",
+			bh.horizon_radius(),
+			format_length(bh.meters(bh.horizon_radius())),
+			distance,
+			format_length(bh.meters(distance)),
+			format_duration(bh.seconds(distance))
+		);
+		self.print_isco();
+		self.print_disk_temperature();
+	}
+
+	/// ISCO of the current spin, where the disk starts unless J was pressed.
+	fn print_isco(&self) {
+		let spin = self.black_hole.spin;
+		let isco = isco_radius(spin);
+		println!(
+			"  {} ISCO r = {:.3} r_s = {}{}",
+			if spin < 0.0 { "retrograde" } else { "prograde" },
+			isco,
+			format_length(self.black_hole.meters(isco)),
+			if self.disk.isco { "" } else { " (disk starts at inner instead)" }
+		);
+	}
+
+	fn print_disk_temperature(&self) {
+		let t_star = self.disk.temperature_scale(self.black_hole.schwarzschild_radius());
+		let flux = FluxProfile::new(self.black_hole.spin, self.disk.outer as f64);
+		println!(
+			"  accretion rate 10^{:.2} Eddington, peak disk temperature {:.0} K",
+			self.disk.log_accretion_rate,
+			flux.peak_temperature(t_star)
+		);
+	}
+
+	fn print_disk_param(&self) {
+		let (min, max, _) = self.disk_param.range();
+		println!(
+			"Disk {}: {} (range {} to {})",
+			self.disk_param.name(),
+			self.disk.get(self.disk_param),
+			min,
+			max
+		);
+		if self.disk_param == DiskParam::LogAccretionRate {
+			self.print_disk_temperature();
+		}
+		if self.disk_param == DiskParam::Inner && self.disk.isco {
+			println!("  (unused while the disk starts at the ISCO; J switches)");
+		}
+	}
+
+	/// CPU counterpart of the metric the shader is currently rendering.
+	fn reference_spacetime(&self) -> Box<dyn Spacetime> {
+		if self.black_hole.spin == 0.0 {
+			Box::new(Schwarzschild::new(1.0))
+		} else {
+			Box::new(Kerr::new(1.0, self.black_hole.spin))
+		}
+	}
 
-			println!("Screenshot saved to {}", filename);
+	/// Trace the ray through the screen centre with the CPU reference integrator
+	/// so its outcome can be compared against what the shader shows.
+	fn trace_reference_ray(&self) {
+		let cam_pos = self.camera.get_position();
+		let view_mat = self.camera.get_view_matrix();
+		let origin = glm::vec3(cam_pos.x as f64, cam_pos.y as f64, cam_pos.z as f64);
+		let forward = glm::vec3(view_mat.m31 as f64, view_mat.m32 as f64, view_mat.m33 as f64);
+		let spacetime = self.reference_spacetime();
+
+		// fixed RK4 next to the adaptive default, to show the cost/accuracy trade-off
+		for method in [Method::Rk4, TraceOptions::default().method] {
+			let opts = TraceOptions { method, record_path: true, ..TraceOptions::default() };
+			let result = spacetime.trace(&origin, &forward, &opts);
+
+			let deflection = glm::angle(&glm::normalize(&forward), &result.final_direction).to_degrees();
+			let path_length: f64 = result.path.windows(2).map(|w| glm::distance(&w[0], &w[1])).sum();
+			println!(
+				"Reference ray ({:?}): {:?} after {} steps ({} rejected, {} evaluations) at r = {:.2}, deflection {:.6}°, path length {:.2} r_s ({})",
+				method,
+				result.termination,
+				result.stats.accepted,
+				result.stats.rejected,
+				result.stats.rhs_evals,
+				glm::length(&result.final_position),
+				deflection,
+				path_length,
+				format_length(self.black_hole.meters(path_length))
+			);
+			println!("  {:?}", result.drift);
 		}
 	}
 
+	/// Trace every pixel of a downscaled copy of the current view on the CPU
+	/// and report how well the constants of motion were conserved.
+	fn survey_drift(&self) {
+		let cam_pos = self.camera.get_position();
+		let origin = glm::vec3(cam_pos.x as f64, cam_pos.y as f64, cam_pos.z as f64);
+		let view = glm::convert::<glm::Mat3, glm::DMat3>(self.camera.get_view_matrix());
+		let (width, height) = self.window_ctx.window.get_framebuffer_size();
+		let (width, height) = ((width as u32 / 8).max(1), (height as u32 / 8).max(1));
+
+		let summary = survey_image(
+			self.reference_spacetime().as_ref(),
+			&origin,
+			&view,
+			self.fov as f64,
+			width,
+			height,
+			&TraceOptions::default(),
+		);
+		println!("Drift survey at {}x{}, spin a/M = {:.2}", width, height, self.black_hole.spin);
+		println!("{}", summary);
+	}
+
 	fn manual(&self) {
 		println!("\n╔════════════════════════════════════════════════════╗");
 		println!("║     Black Hole 3D Renderer - Controls              ║");
@@ -424,14 +1209,36 @@ impl App {
 		println!("║   Q/E Keys          : Roll camera left/right       ║");
 		println!("║   R Key             : Reset camera roll            ║");
 		println!("║   P Key             : Take screenshot              ║");
+		println!("║   Shift+P Key       : HDR screenshot (.exr)        ║");
+		println!("║   N Key             : CPU-rendered screenshot      ║");
+		println!("║   U Key             : Poster render (4x window)    ║");
+		println!("║   Y Key             : Start/stop video recording   ║");
+		println!("║   S Key             : Save scene to scenes/        ║");
+		println!("║   V Key             : CPU reference centre ray     ║");
+		println!("║   B Key             : CPU drift survey of the view ║");
 		println!("║   T Key             : Active/passive mouse tracking║");
 		println!("║   C Key             : Toggle FreeCam/LockedCam     ║");
 		println!("║   Arrow Keys        : Move camera (FreeCam only)   ║");
 		println!("║   F / F12 Keys       : Toggle fullscreen mode      ║");
+		println!("║   F1 Key            : Show/hide the HUD            ║");
 		println!("╠════════════════════════════════════════════════════╣");
 		println!("║ RENDERING                                          ║");
 		println!("║   D Key             : Toggle accretion disk        ║");
 		println!("║   G Key             : Toggle gravitational lensing ║");
+		println!("║   X Key             : Toggle Doppler/redshift      ║");
+		println!("║   O Key             : Colour map/blackbody emission║");
+		println!("║   J Key             : Inner edge at ISCO/at inner  ║");
+		println!("║   H Key             : Next tone mapping operator   ║");
+		println!("║   -/= Keys          : Decrease/increase exposure   ║");
+		println!("║   A Key             : Toggle auto exposure         ║");
+		println!("║   Z Key             : Toggle bloom                 ║");
+		println!("║   ,/. Keys          : Decrease/increase bloom      ║");
+		println!("║   K/L Keys          : Decrease/increase spin a/M   ║");
+		println!("║   M Key             : Next mass (Sgr A*, M87*, ...)║");
+		println!("║   I Key             : Print mass and scale         ║");
+		println!("║   W Key             : Reload shaders (also on save)║");
+		println!("║   Tab Key           : Select disk parameter        ║");
+		println!("║   [/] Keys          : Decrease/increase parameter  ║");
 		println!("╠════════════════════════════════════════════════════╣");
 		println!("║ ESC                 : Exit                         ║");
 		println!("╚════════════════════════════════════════════════════╝\n");
@@ -439,3 +1246,11 @@ impl App {
 		println!("Camera mode: Free Orbit");
 	}
 }
+
+/// Write the scene file that goes with a saved screenshot.
+fn save_sidecar(metadata: &RenderMetadata, image: &Path) {
+	match metadata.save_sidecar(image) {
+		Ok(path) => println!("Scene saved to {}", path.display()),
+		Err(e) => println!("ERROR: {}", e),
+	}
+}
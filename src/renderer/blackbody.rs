@@ -0,0 +1,116 @@
+use crate::gl_bindings::*;
+use gl::types::GLuint;
+
+/// Number of entries in the blackbody lookup table.
+pub const LUT_SIZE: usize = 256;
+/// Temperature range of the table in kelvin; entries are spaced evenly in
+/// `ln T`, so `blackhole.frag` maps `T` to `u = ln(T / MIN) / ln(MAX / MIN)`.
+pub const LUT_MIN_TEMPERATURE: f64 = 1000.0;
+pub const LUT_MAX_TEMPERATURE: f64 = 40000.0;
+
+/// Second radiation constant `hc/k` in m·K.
+const C2: f64 = 1.438777e-2;
+
+/// Linear sRGB colour of a blackbody at `temperature` kelvin, scaled to unit
+/// luminance so the table only carries the colour. Out-of-gamut (negative)
+/// components are clipped.
+pub fn blackbody_rgb(temperature: f64) -> [f32; 3] {
+    let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+    for nm in (380..=780).step_by(5) {
+        let lambda = nm as f64 * 1e-9;
+        // Planck's law up to a constant factor
+        let radiance = 1.0 / (lambda.powi(5) * ((C2 / (lambda * temperature)).exp() - 1.0));
+        let (xb, yb, zb) = color_matching(nm as f64);
+        x += radiance * xb;
+        y += radiance * yb;
+        z += radiance * zb;
+    }
+
+    let rgb = [
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    ];
+    rgb.map(|c| (c / y).max(0.0) as f32)
+}
+
+/// `LUT_SIZE` colours from `LUT_MIN_TEMPERATURE` to `LUT_MAX_TEMPERATURE`.
+pub fn lut() -> Vec<[f32; 3]> {
+    let ratio = LUT_MAX_TEMPERATURE / LUT_MIN_TEMPERATURE;
+    (0..LUT_SIZE)
+        .map(|i| blackbody_rgb(LUT_MIN_TEMPERATURE * ratio.powf(i as f64 / (LUT_SIZE - 1) as f64)))
+        .collect()
+}
+
+/// Upload `lut()` as a `LUT_SIZE`×1 float texture with linear filtering.
+pub fn create_lut_texture() -> GLuint {
+    let data: Vec<f32> = lut().into_iter().flatten().collect();
+
+    let mut texture_id: GLuint = 0;
+    unsafe {
+        GenTextures(1, &mut texture_id);
+        BindTexture(TEXTURE_2D, texture_id);
+        TexImage2D(
+            TEXTURE_2D,
+            0,
+            RGB32F as i32,
+            LUT_SIZE as i32,
+            1,
+            0,
+            RGB,
+            FLOAT,
+            data.as_ptr() as *const _,
+        );
+        TexParameteri(TEXTURE_2D, TEXTURE_MIN_FILTER, LINEAR as i32);
+        TexParameteri(TEXTURE_2D, TEXTURE_MAG_FILTER, LINEAR as i32);
+        TexParameteri(TEXTURE_2D, TEXTURE_WRAP_S, CLAMP_TO_EDGE as i32);
+        TexParameteri(TEXTURE_2D, TEXTURE_WRAP_T, CLAMP_TO_EDGE as i32);
+    }
+    texture_id
+}
+
+/// CIE 1931 2° colour matching functions at `nm` nanometres, using the
+/// multi-lobe Gaussian fit of Wyman, Sloan & Shirley (2013).
+fn color_matching(nm: f64) -> (f64, f64, f64) {
+    let g = |mu: f64, sigma_lo: f64, sigma_hi: f64| {
+        let t = (nm - mu) / if nm < mu { sigma_lo } else { sigma_hi };
+        (-0.5 * t * t).exp()
+    };
+    (
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn about_6500_kelvin_is_white() {
+        let [r, g, b] = blackbody_rgb(6500.0);
+        for c in [r, g, b] {
+            assert!((c - 1.0).abs() < 0.1, "{:?}", [r, g, b]);
+        }
+    }
+
+    #[test]
+    fn cool_bodies_are_red_and_hot_ones_blue() {
+        let [r, g, b] = blackbody_rgb(2000.0);
+        assert!(r > g && g > b, "{:?}", [r, g, b]);
+        let [r, g, b] = blackbody_rgb(30000.0);
+        assert!(b > g && g > r, "{:?}", [r, g, b]);
+    }
+
+    #[test]
+    fn lut_spans_the_temperature_range() {
+        let table = lut();
+        assert_eq!(table.len(), LUT_SIZE);
+        assert_eq!(table[0], blackbody_rgb(LUT_MIN_TEMPERATURE));
+        let last = blackbody_rgb(LUT_MAX_TEMPERATURE);
+        for (a, b) in table[LUT_SIZE - 1].iter().zip(last) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+}
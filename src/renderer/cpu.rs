@@ -0,0 +1,649 @@
+use std::path::Path;
+
+use image::{RgbImage, RgbaImage};
+use nalgebra_glm as glm;
+use rayon::prelude::*;
+
+use crate::cli::Backend;
+use crate::metadata::RenderMetadata;
+use crate::physics::{camera_ray, integrator::rk4_step, kerr::{self, Kerr}};
+use crate::renderer::blackbody::{self, LUT_MAX_TEMPERATURE, LUT_MIN_TEMPERATURE};
+use crate::renderer::disk::{EmissionMode, FluxProfile, FLUX_LUT_SIZE};
+use crate::renderer::exposure::{auto_exposure, luminance};
+use crate::renderer::frame::FrameParams;
+use crate::renderer::post::bloom_level_sizes;
+use crate::renderer::skybox::load_faces;
+use crate::renderer::utils::{is_hdr_path, save_rgba, save_rgba_f32};
+use crate::scene::Scene;
+use crate::shader::Define;
+
+// Mirrors of the constants in `shaders/blackhole.frag`.
+const R_S: f64 = 1.0;
+const STEP_SIZE: f64 = 0.06;
+const MAX_CAP: i32 = 2000;
+const NORM_INTERVAL: i32 = 4;
+const BEAMING_EXPONENT: f64 = 4.0;
+// Blur kernel of `shaders/bloom.frag`.
+const BLOOM_WEIGHTS: [f64; 5] = [70.0 / 256.0, 56.0 / 256.0, 28.0 / 256.0, 8.0 / 256.0, 1.0 / 256.0];
+
+/// Linear radiance of the pixels of one tile, row by row, keyed by the tile's
+/// top-left corner.
+type Tile = ((u32, u32), Vec<glm::DVec3>);
+
+/// Side length in pixels of the square tiles handed out to worker threads.
+const TILE_SIZE: u32 = 32;
+
+/// Multithreaded CPU port of `shaders/blackhole.frag`.
+///
+/// Each pixel runs the same march as `traceRay`/`traceRayKerr`, with the same
+/// step heuristics and volumetric disk, but in f64 and with textures sampled
+/// at full resolution. Tiles are spread over all cores with rayon.
+pub struct CpuRenderer {
+    /// Skybox faces in `TEXTURE_CUBE_MAP_POSITIVE_X + i` order, oriented as uploaded.
+    skybox: Vec<RgbImage>,
+    /// Colour map oriented as uploaded by `load_texture`.
+    color_map: RgbImage,
+    blackbody_lut: Vec<[f32; 3]>,
+    /// `ADISK_LIT` and `ADISK_PARTICLE`, after `--define` overrides.
+    adisk_lit: f64,
+    adisk_particle: f64,
+}
+
+impl CpuRenderer {
+    /// Load the textures and apply the `--define` overrides the shader
+    /// understands; any other define is rejected, since the CPU port has no
+    /// preprocessor to hand it to.
+    pub fn load<P: AsRef<Path>>(skybox_dir: P, color_map_path: P, defines: &[Define]) -> Result<Self, String> {
+        let (mut adisk_lit, mut adisk_particle) = (1.0, 1.0);
+        for (name, value) in defines {
+            let target = match name.as_str() {
+                "ADISK_LIT" => &mut adisk_lit,
+                "ADISK_PARTICLE" => &mut adisk_particle,
+                _ => return Err(format!("--define {} is not supported by the cpu backend", name)),
+            };
+            *target = value.trim().parse().map_err(|_| format!("invalid value '{}' for {} on the cpu backend", value, name))?;
+        }
+        let skybox = load_faces(skybox_dir)?;
+        let color_map = image::open(color_map_path.as_ref())
+            .map_err(|e| format!("Failed to load {:?}: {}", color_map_path.as_ref(), e))?
+            .flipv()
+            .to_rgb8();
+        Ok(Self { skybox, color_map, blackbody_lut: blackbody::lut(), adisk_lit, adisk_particle })
+    }
+
+    /// Render a frame, with bloom, exposure and tone mapping applied the same
+    /// way as the post-processing passes. Auto exposure is metered on the
+    /// frame itself, before bloom.
+    pub fn render(&self, frame: &FrameParams) -> RgbaImage {
+        let (width, height) = (frame.width, frame.height);
+        let scene = self.trace(frame);
+
+        let mut ev = frame.exposure.ev;
+        if frame.exposure.auto {
+            ev += auto_exposure(scene.pixels.iter().map(|c| [c.x, c.y, c.z]));
+        }
+        let scale = 2f64.powf(ev as f64);
+        let tonemap = frame.exposure.tonemap;
+        let glow = frame.bloom.enabled.then(|| bloom(&scene, frame.bloom.threshold as f64));
+        let intensity = frame.bloom.intensity as f64;
+
+        RgbaImage::from_fn(width, height, |x, y| {
+            let mut color = scene.pixels[(y * width + x) as usize];
+            if let Some(glow) = &glow {
+                color += intensity * glow.sample((x as f64 + 0.5) / width as f64, (y as f64 + 0.5) / height as f64);
+            }
+            // exposure and tone mapping, then gamma
+            let rgb = color.map(|c| tonemap.apply(c * scale).powf(1.0 / 2.2));
+            image::Rgba([to_unorm8(rgb.x), to_unorm8(rgb.y), to_unorm8(rgb.z), 255])
+        })
+    }
+
+    /// Linear radiance of a frame as top-to-bottom RGBA rows, before bloom
+    /// and tone mapping, like the GPU renderer's scene target.
+    pub fn render_hdr(&self, frame: &FrameParams) -> Vec<f32> {
+        self.trace(frame).pixels.iter().flat_map(|c| [c.x as f32, c.y as f32, c.z as f32, 1.0]).collect()
+    }
+
+    /// Render `scene` at `width`×`height` and save it to `path` like
+    /// `App::render_to_file`, with `shader_hash` in the PNG metadata.
+    pub fn render_to_file(&self, scene: &Scene, width: u32, height: u32, path: &Path, shader_hash: String) -> Result<(), String> {
+        let frame = FrameParams::from_scene(scene, width, height);
+        if is_hdr_path(path) {
+            return save_rgba_f32(path, width, height, self.render_hdr(&frame));
+        }
+        let metadata = RenderMetadata::new(scene.clone(), shader_hash, Backend::Cpu);
+        save_rgba(path, width, height, self.render(&frame).into_raw(), &metadata)
+    }
+
+    /// Trace every pixel of a frame, spreading tiles over all cores.
+    fn trace(&self, frame: &FrameParams) -> HdrImage {
+        let (width, height) = (frame.width, frame.height);
+        let tiles: Vec<(u32, u32)> = (0..height)
+            .step_by(TILE_SIZE as usize)
+            .flat_map(|y| (0..width).step_by(TILE_SIZE as usize).map(move |x| (x, y)))
+            .collect();
+
+        let rendered: Vec<Tile> = tiles
+            .into_par_iter()
+            .map(|(x0, y0)| {
+                let mut pixels = Vec::with_capacity((TILE_SIZE * TILE_SIZE) as usize);
+                for y in y0..(y0 + TILE_SIZE).min(height) {
+                    for x in x0..(x0 + TILE_SIZE).min(width) {
+                        pixels.push(self.shade_pixel(frame, x, y));
+                    }
+                }
+                ((x0, y0), pixels)
+            })
+            .collect();
+
+        let mut scene = HdrImage { width, height, pixels: vec![glm::DVec3::zeros(); (width * height) as usize] };
+        for ((x0, y0), pixels) in rendered {
+            let tile_width = (x0 + TILE_SIZE).min(width) - x0;
+            for (i, color) in pixels.into_iter().enumerate() {
+                let i = i as u32;
+                let (x, y) = (x0 + i % tile_width, y0 + i / tile_width);
+                scene.pixels[(y * width + x) as usize] = color;
+            }
+        }
+        scene
+    }
+
+    /// `main()` of the shader for image pixel `(x, y)`, counted from the top
+    /// left: the linear radiance it writes into the scene target.
+    fn shade_pixel(&self, frame: &FrameParams, x: u32, y: u32) -> glm::DVec3 {
+        let view = glm::convert::<glm::Mat3, glm::DMat3>(frame.view_matrix);
+        let pos = glm::convert::<glm::Vec3, glm::DVec3>(frame.camera_pos);
+        // gl_FragCoord counts rows from the bottom
+        let frag_y = (frame.height - 1 - y) as f64 + 0.5;
+        let dir = camera_ray(&view, frame.fov as f64, x as f64 + 0.5, frag_y, frame.width as f64, frame.height as f64);
+
+        self.trace_ray(frame, pos, dir)
+    }
+
+    fn trace_ray(&self, frame: &FrameParams, mut pos: glm::DVec3, dir: glm::DVec3) -> glm::DVec3 {
+        if frame.gravitational_lensing && frame.spin.abs() > 1e-4 {
+            return self.trace_ray_kerr(frame, pos, dir);
+        }
+
+        let mut color = glm::DVec3::zeros();
+        let mut alpha = 1.0;
+
+        let dist_scale = (glm::length(&pos) / 4.0).clamp(0.6, 3.0);
+        let step = STEP_SIZE * dist_scale * frame.step_scale as f64;
+
+        // the shader also bends a copy of the view direction for the skybox
+        // lookup, but it receives identical updates so one vector suffices
+        let mut ray_dir = glm::normalize(&dir);
+
+        // L_z / E of the photon arriving at a static camera along -ray_dir
+        let lambda = (pos.x * ray_dir.z - pos.z * ray_dir.x) / (1.0 - R_S / glm::length(&pos)).max(1e-4).sqrt();
+
+        let max_dist = glm::length(&pos) + frame.disk.outer as f64 * 2.0;
+        let mut traveled = 0.0;
+
+        for i in 0..frame.max_iter.min(MAX_CAP) {
+            let h2 = glm::length2(&glm::cross(&pos, &ray_dir));
+
+            if frame.render_disk && alpha > 0.001 && glm::dot(&pos, &pos) >= R_S * R_S {
+                self.adisk_color(frame, &pos, &mut color, &mut alpha, step, lambda);
+                if alpha < 0.001 {
+                    return color;
+                }
+            }
+
+            if frame.gravitational_lensing {
+                let r2 = glm::dot(&pos, &pos);
+                let r5 = r2 * r2 * (r2 + 1e-9).sqrt();
+                ray_dir += pos * (-1.5 * h2 / r5) * step;
+                if i % NORM_INTERVAL == 0 {
+                    ray_dir = glm::normalize(&ray_dir);
+                }
+            }
+
+            pos += ray_dir * step;
+            traveled += step;
+
+            if glm::dot(&pos, &pos) < R_S * R_S {
+                return color;
+            }
+            if traveled > max_dist {
+                break;
+            }
+        }
+
+        color + self.sample_skybox(&glm::normalize(&ray_dir)) * alpha
+    }
+
+    /// `traceRayKerr`: RK4 in Kerr–Schild coordinates using the reference
+    /// right-hand side from `physics::kerr`.
+    fn trace_ray_kerr(&self, frame: &FrameParams, pos: glm::DVec3, dir: glm::DVec3) -> glm::DVec3 {
+        let mut color = glm::DVec3::zeros();
+        let mut alpha = 1.0;
+
+        let kerr = Kerr::new(R_S, frame.spin as f64);
+        let mut state = kerr.init_ray(&pos, &dir);
+        // the photon reaching the camera travels opposite to the traced ray
+        let lambda = -(state[0] * state[4] - state[1] * state[3]);
+        let horizon = kerr.horizon_radius();
+
+        let dist_scale = (glm::length(&pos) / 4.0).clamp(0.6, 3.0);
+        let base_step = STEP_SIZE * dist_scale * frame.step_scale as f64;
+
+        let max_dist = glm::length(&pos) + frame.disk.outer as f64 * 2.0;
+        let mut traveled = 0.0;
+        let mut ray_dir = glm::normalize(&dir);
+
+        for _ in 0..frame.max_iter.min(MAX_CAP) {
+            let x = glm::vec3(state[0], state[1], state[2]);
+            let step = base_step.min(0.1 * kerr.radius(&x));
+
+            if frame.render_disk && alpha > 0.001 {
+                self.adisk_color(frame, &kerr::to_world(&x), &mut color, &mut alpha, step, lambda);
+                if alpha < 0.001 {
+                    return color;
+                }
+            }
+
+            state = rk4_step(|y| kerr.rhs(y), &state, step);
+            let moved_by = glm::vec3(state[0], state[1], state[2]) - x;
+            let moved = glm::length(&moved_by);
+            if moved > 0.0 {
+                ray_dir = kerr::to_world(&moved_by) / moved;
+            }
+            traveled += moved;
+
+            if kerr.radius(&glm::vec3(state[0], state[1], state[2])) < horizon {
+                return color;
+            }
+            if traveled > max_dist {
+                break;
+            }
+        }
+
+        color + self.sample_skybox(&ray_dir) * alpha
+    }
+
+    /// Emission and Beer–Lambert absorption of one march step through the
+    /// disk, seen along a photon with `L_z / E = lambda`.
+    fn adisk_color(&self, frame: &FrameParams, pos: &glm::DVec3, color: &mut glm::DVec3, alpha: &mut f64, step: f64, lambda: f64) {
+        let disk = &frame.disk;
+        let height = disk.height as f64;
+        let speed = disk.speed as f64;
+        let inner_radius = disk.inner_radius(frame.spin as f64);
+        let outer_radius = disk.outer as f64;
+
+        let scaled = glm::vec3(pos.x / outer_radius, pos.y / height, pos.z / outer_radius);
+        let mut density = (1.0 - glm::length(&scaled)).max(0.0);
+        if density < 0.001 {
+            return;
+        }
+
+        density *= (1.0 - pos.y.abs() / height).powf(disk.density_v as f64);
+        density *= smoothstep(inner_radius, inner_radius * 1.1, pos.x.hypot(pos.z));
+        if density < 0.001 {
+            return;
+        }
+
+        let rho = glm::length(pos);
+        let mut theta = pos.z.atan2(pos.x);
+        let phi = pos.y.abs();
+
+        let g = if frame.redshift { redshift_factor(frame.spin as f64, pos, lambda).max(1e-3) } else { 1.0 };
+        let (dust_color, beaming) = match frame.emission_mode {
+            EmissionMode::Blackbody => {
+                let t_peak = frame.flux.peak_temperature(disk.temperature_scale(frame.rs_meters as f64));
+                let flux = disk_flux(&frame.flux, rho);
+                let t_observed = g * t_peak * flux.powf(0.25);
+                (self.blackbody_color(t_observed), flux * g.powf(BEAMING_EXPONENT))
+            }
+            EmissionMode::ColorMap => {
+                let shifted_rho = rho * g.powf(-4.0 / 3.0);
+                let v_radial = ((shifted_rho - inner_radius) / (outer_radius - inner_radius)).clamp(0.0, 1.0);
+                (sample_bilinear(&self.color_map, v_radial, 0.5, Wrap::Repeat), g.powf(BEAMING_EXPONENT))
+            }
+        };
+
+        let mut noise = 1.0;
+        for i in 0..frame.noise_lod.min(8) {
+            let f = (i * i) as f64;
+            let theta_t = theta + frame.time as f64 * speed * 0.5;
+            noise *= 0.5 * snoise(&(glm::vec3(rho, theta_t, phi) * f * disk.noise_scale as f64)) + 0.5;
+            if i % 2 == 0 {
+                theta += speed * 0.01;
+            } else {
+                theta -= speed * 0.01;
+            }
+        }
+
+        density *= 1.0 / rho.powf(disk.density_h as f64);
+        density *= disk.density_scale as f64;
+
+        // particle-lite fallback
+        let emission = if self.adisk_particle < 0.5 {
+            dust_color * (density * 0.04 * noise.abs() * beaming)
+        } else {
+            dust_color * (density * self.adisk_lit * noise.abs() * beaming)
+        };
+        let sigma = disk.absorption as f64;
+        let trans = (-density * sigma * step).exp();
+        *color += emission * ((1.0 - trans) * *alpha);
+        *alpha *= trans;
+    }
+
+    /// `blackbodyColor`: linear interpolation in the lookup table, as the
+    /// sampler does between texel centres.
+    fn blackbody_color(&self, temperature: f64) -> glm::DVec3 {
+        let u = (temperature / LUT_MIN_TEMPERATURE).ln() / (LUT_MAX_TEMPERATURE / LUT_MIN_TEMPERATURE).ln();
+        let x = u.clamp(0.0, 1.0) * (self.blackbody_lut.len() - 1) as f64;
+        let i = (x as usize).min(self.blackbody_lut.len() - 2);
+        let texel = |i: usize| glm::convert::<glm::Vec3, glm::DVec3>(glm::Vec3::from(self.blackbody_lut[i]));
+        glm::lerp(&texel(i), &texel(i + 1), x - i as f64)
+    }
+
+    /// Cubemap lookup following the face selection rules of the GL spec.
+    fn sample_skybox(&self, dir: &glm::DVec3) -> glm::DVec3 {
+        let (ax, ay, az) = (dir.x.abs(), dir.y.abs(), dir.z.abs());
+        let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+            if dir.x > 0.0 { (0, -dir.z, -dir.y, ax) } else { (1, dir.z, -dir.y, ax) }
+        } else if ay >= az {
+            if dir.y > 0.0 { (2, dir.x, dir.z, ay) } else { (3, dir.x, -dir.z, ay) }
+        } else if dir.z > 0.0 {
+            (4, dir.x, -dir.y, az)
+        } else {
+            (5, -dir.x, -dir.y, az)
+        };
+        sample_bilinear(&self.skybox[face], 0.5 * (sc / ma + 1.0), 0.5 * (tc / ma + 1.0), Wrap::ClampToEdge)
+    }
+}
+
+/// `diskFlux`: the flux table looked up with linear filtering.
+fn disk_flux(profile: &FluxProfile, r: f64) -> f64 {
+    let (r0, r1) = profile.range;
+    let u = (r / r0).ln() / (r1 / r0).ln();
+    let x = u.clamp(0.0, 1.0) * (FLUX_LUT_SIZE - 1) as f64;
+    let i = (x as usize).min(FLUX_LUT_SIZE - 2);
+    let t = x - i as f64;
+    profile.table[i] as f64 * (1.0 - t) + profile.table[i + 1] as f64 * t
+}
+
+/// Linear radiance of a frame or a bloom level, row by row from the top.
+struct HdrImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<glm::DVec3>,
+}
+
+impl HdrImage {
+    fn from_fn<F: Fn(u32, u32) -> glm::DVec3>(width: u32, height: u32, f: F) -> Self {
+        let pixels = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| f(x, y)).collect();
+        Self { width, height, pixels }
+    }
+
+    /// Pixel `(x, y)`, clamped to the edge.
+    fn get(&self, x: i64, y: i64) -> glm::DVec3 {
+        let x = x.clamp(0, self.width as i64 - 1);
+        let y = y.clamp(0, self.height as i64 - 1);
+        self.pixels[(y * self.width as i64 + x) as usize]
+    }
+
+    /// Linearly filtered lookup at `(s, t)`, clamped to the edge like the
+    /// float targets of `PostProcess`.
+    fn sample(&self, s: f64, t: f64) -> glm::DVec3 {
+        let x = s * self.width as f64 - 0.5;
+        let y = t * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = glm::lerp(&self.get(x0, y0), &self.get(x0 + 1, y0), fx);
+        let bottom = glm::lerp(&self.get(x0, y0 + 1), &self.get(x0 + 1, y0 + 1), fx);
+        glm::lerp(&top, &bottom, fy)
+    }
+
+    /// Draw into a `width`×`height` target, sampling at each pixel's centre.
+    fn resample(&self, width: u32, height: u32) -> Self {
+        Self::from_fn(width, height, |x, y| self.sample((x as f64 + 0.5) / width as f64, (y as f64 + 0.5) / height as f64))
+    }
+
+    /// One direction of the separable blur of `bloom.frag`, `(dx, dy)` pixels per tap.
+    fn blur(&self, dx: i64, dy: i64) -> Self {
+        Self::from_fn(self.width, self.height, |x, y| {
+            let (x, y) = (x as i64, y as i64);
+            let mut c = BLOOM_WEIGHTS[0] * self.get(x, y);
+            for (i, &weight) in BLOOM_WEIGHTS.iter().enumerate().skip(1) {
+                let i = i as i64;
+                c += weight * (self.get(x + i * dx, y + i * dy) + self.get(x - i * dx, y - i * dy));
+            }
+            c
+        })
+    }
+}
+
+/// `PostProcess::bloom`: the first level of the bloom chain once the bright
+/// parts of `scene` are blurred down the chain and added back up.
+fn bloom(scene: &HdrImage, threshold: f64) -> HdrImage {
+    let mut levels: Vec<HdrImage> = Vec::new();
+    for (width, height) in bloom_level_sizes(scene.width, scene.height) {
+        let level = match levels.last() {
+            Some(previous) => previous.resample(width, height),
+            None => {
+                // bright pass at half resolution
+                let mut level = scene.resample(width, height);
+                for c in &mut level.pixels {
+                    let lum = luminance([c.x, c.y, c.z]);
+                    *c *= (lum - threshold).max(0.0) / lum.max(1e-4);
+                }
+                level
+            }
+        };
+        levels.push(level);
+    }
+
+    for level in &mut levels {
+        *level = level.blur(1, 0).blur(0, 1);
+    }
+
+    // add each level onto the next larger one, smallest first
+    for i in (1..levels.len()).rev() {
+        let (larger, smaller) = levels.split_at_mut(i);
+        let larger = &mut larger[i - 1];
+        let upsampled = smaller[0].resample(larger.width, larger.height);
+        for (c, u) in larger.pixels.iter_mut().zip(upsampled.pixels) {
+            *c += u;
+        }
+    }
+    levels.swap_remove(0)
+}
+
+#[derive(Clone, Copy)]
+enum Wrap {
+    Repeat,
+    ClampToEdge,
+}
+
+/// Linearly filtered lookup at texture coordinates `(s, t)`, where row 0 of
+/// the image is `t = 0` as in an uploaded texture.
+fn sample_bilinear(img: &RgbImage, s: f64, t: f64, wrap: Wrap) -> glm::DVec3 {
+    let (width, height) = (img.width() as i64, img.height() as i64);
+    let x = s * width as f64 - 0.5;
+    let y = t * height as f64 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let texel = |i: i64, j: i64| {
+        let (i, j) = match wrap {
+            Wrap::Repeat => (i.rem_euclid(width), j.rem_euclid(height)),
+            Wrap::ClampToEdge => (i.clamp(0, width - 1), j.clamp(0, height - 1)),
+        };
+        let p = img.get_pixel(i as u32, j as u32);
+        glm::vec3(p[0] as f64, p[1] as f64, p[2] as f64) / 255.0
+    };
+
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let top = glm::lerp(&texel(x0, y0), &texel(x0 + 1, y0), fx);
+    let bottom = glm::lerp(&texel(x0, y0 + 1), &texel(x0 + 1, y0 + 1), fx);
+    glm::lerp(&top, &bottom, fy)
+}
+
+fn to_unorm8(c: f64) -> u8 {
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// `redshiftFactor`: `ν_obs/ν_emit` of Keplerian gas at world position `pos`
+/// seen along a photon with `L_z / E = lambda`, including gravitational redshift.
+fn redshift_factor(spin: f64, pos: &glm::DVec3, lambda: f64) -> f64 {
+    let kerr = Kerr::new(R_S, spin);
+    let (m, a) = (kerr.mass, kerr.a());
+    let x = kerr::to_kerr_schild(pos);
+    let r = kerr.radius(&x);
+    let cos2 = x.z * x.z / (r * r);
+    let sin2 = 1.0 - cos2;
+    let sigma = r * r + a * a * cos2;
+    let omega = m.sqrt() / (r.powf(1.5) + a * m.sqrt());
+
+    let g_tt = -(1.0 - 2.0 * m * r / sigma);
+    let g_tphi = -2.0 * m * a * r * sin2 / sigma;
+    let g_phiphi = (r * r + a * a + 2.0 * m * a * a * r * sin2 / sigma) * sin2;
+    let norm = -(g_tt + 2.0 * omega * g_tphi + omega * omega * g_phiphi);
+    if norm <= 0.0 {
+        return 0.0;
+    }
+    norm.sqrt() / (1.0 - omega * lambda)
+}
+
+/// GLSL `mod(x, 289.0)` of the permutation polynomial.
+fn permute(x: f64) -> f64 {
+    ((x * 34.0 + 1.0) * x).rem_euclid(289.0)
+}
+
+/// 3D simplex noise, a corner-by-corner port of `snoise` in the shader.
+fn snoise(v: &glm::DVec3) -> f64 {
+    const C: (f64, f64) = (1.0 / 6.0, 1.0 / 3.0);
+    // 1/7 * D.wyz - D.xzx for D = (0, 0.5, 1, 2)
+    const NS: (f64, f64, f64) = (2.0 / 7.0, 0.5 / 7.0 - 1.0, 1.0 / 7.0);
+
+    // First corner
+    let i = (v + glm::DVec3::repeat(v.sum() * C.1)).map(f64::floor);
+    let x0 = v - i + glm::DVec3::repeat(i.sum() * C.0);
+
+    // Other corners
+    let step = |edge: f64, x: f64| if x < edge { 0.0 } else { 1.0 };
+    let g = glm::vec3(step(x0.y, x0.x), step(x0.z, x0.y), step(x0.x, x0.z));
+    let l = glm::vec3(1.0, 1.0, 1.0) - g;
+    let l_zxy = glm::vec3(l.z, l.x, l.y);
+    let i1 = g.inf(&l_zxy);
+    let i2 = g.sup(&l_zxy);
+
+    let offsets = [glm::DVec3::zeros(), i1, i2, glm::vec3(1.0, 1.0, 1.0)];
+    let corners = [
+        x0,
+        x0 - i1 + glm::DVec3::repeat(C.0),
+        x0 - i2 + glm::DVec3::repeat(C.1),
+        x0 - glm::DVec3::repeat(0.5),
+    ];
+    let i = i.map(|c| c.rem_euclid(289.0));
+
+    let mut total = 0.0;
+    for (o, x) in offsets.iter().zip(corners.iter()) {
+        let p = permute(permute(permute(i.z + o.z) + i.y + o.y) + i.x + o.x);
+
+        // Gradients: 7x7 points over a square, mapped onto an octahedron
+        let j = p - 49.0 * (p * NS.2 * NS.2).floor();
+        let gx_ = (j * NS.2).floor();
+        let gy_ = (j - 7.0 * gx_).floor();
+        let gx = gx_ * NS.0 + NS.1;
+        let gy = gy_ * NS.0 + NS.1;
+        let h = 1.0 - gx.abs() - gy.abs();
+        let sh = if h > 0.0 { 0.0 } else { -1.0 };
+        let grad = glm::vec3(gx + (gx.floor() * 2.0 + 1.0) * sh, gy + (gy.floor() * 2.0 + 1.0) * sh, h);
+
+        let norm = 1.79284291400159 - 0.85373472095314 * glm::dot(&grad, &grad);
+        let m = (0.6 - glm::dot(x, x)).max(0.0);
+        total += m * m * m * m * glm::dot(&(grad * norm), x);
+    }
+    42.0 * total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Renderer whose skybox face `i` is a solid `10 * (i + 1)` grey.
+    fn renderer() -> CpuRenderer {
+        let skybox = (0..6).map(|i| RgbImage::from_pixel(2, 2, image::Rgb([10 * (i + 1); 3]))).collect();
+        let color_map = RgbImage::from_pixel(1, 1, image::Rgb([255; 3]));
+        CpuRenderer { skybox, color_map, blackbody_lut: blackbody::lut(), adisk_lit: 1.0, adisk_particle: 1.0 }
+    }
+
+    #[test]
+    fn skybox_lookups_pick_the_face_of_the_major_axis() {
+        let cpu = renderer();
+        let axes = [
+            glm::vec3(1.0, 0.2, -0.3),
+            glm::vec3(-1.0, 0.2, 0.3),
+            glm::vec3(0.1, 1.0, -0.3),
+            glm::vec3(0.1, -1.0, 0.3),
+            glm::vec3(-0.2, 0.3, 1.0),
+            glm::vec3(0.2, -0.3, -1.0),
+        ];
+        for (face, dir) in axes.iter().enumerate() {
+            let color = cpu.sample_skybox(dir);
+            assert!((color.x * 255.0 - 10.0 * (face + 1) as f64).abs() < 1e-9, "{:?} -> {}", dir, color.x * 255.0);
+        }
+    }
+
+    #[test]
+    fn disk_flux_clamps_to_the_ends_of_the_table() {
+        let profile = FluxProfile::new(0.0, 20.0);
+        let (r0, r1) = profile.range;
+        let (first, last) = (profile.table[0] as f64, profile.table[FLUX_LUT_SIZE - 1] as f64);
+        assert_eq!(disk_flux(&profile, r0), first);
+        assert_eq!(disk_flux(&profile, 0.5 * r0), first);
+        assert!((disk_flux(&profile, r1) - last).abs() < 1e-6);
+        assert_eq!(disk_flux(&profile, 10.0 * r1), last);
+    }
+
+    #[test]
+    fn simplex_noise_matches_the_shader_and_stays_in_range() {
+        // values drawn by `snoise` in shaders/common/noise.glsl
+        let known = [
+            (glm::vec3(0.0, 0.0, 0.0), -0.412199),
+            (glm::vec3(0.5, 1.25, -2.0), 0.525830),
+            (glm::vec3(10.3, -4.7, 2.2), 0.412146),
+            (glm::vec3(3.7, 0.2, -1.9), 0.194311),
+        ];
+        for (v, expected) in known {
+            assert!((snoise(&v) - expected).abs() < 2e-3, "{:?}: {}", v, snoise(&v));
+        }
+        for i in 0..2000 {
+            let t = i as f64 * 0.37;
+            let n = snoise(&glm::vec3(t.sin() * 40.0, t * 0.11, t.cos() * 13.0 - t));
+            assert!((-1.0..=1.0).contains(&n), "{}", n);
+        }
+    }
+
+    #[test]
+    fn redshift_vanishes_far_from_a_static_hole() {
+        let far = redshift_factor(0.0, &glm::vec3(1e6, 0.0, 0.0), 0.0);
+        assert!((far - 1.0).abs() < 1e-5, "{}", far);
+        let near = redshift_factor(0.0, &glm::vec3(10.0, 0.0, 0.0), 0.0);
+        assert!(near < 1.0, "{}", near);
+    }
+
+    #[test]
+    fn bloom_of_a_uniform_image_keeps_its_energy_per_level() {
+        let (width, height) = (64, 48);
+        let color = glm::vec3(0.5, 1.0, 2.0);
+        let glow = bloom(&HdrImage::from_fn(width, height, |_, _| color), 0.0);
+        // each level blurs without gaining or losing light, and all of them add up
+        let levels = bloom_level_sizes(width, height).len() as f64;
+        assert_eq!((glow.width, glow.height), bloom_level_sizes(width, height)[0]);
+        for c in &glow.pixels {
+            assert!((c - color * levels).norm() < 1e-9, "{:?}", c);
+        }
+    }
+}
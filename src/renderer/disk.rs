@@ -0,0 +1,384 @@
+use std::str::FromStr;
+
+use crate::gl_bindings::*;
+use gl::types::GLuint;
+
+/// `3c³m_p / (8σ_Tσ_SB·η)` in K⁴·m for a radiative efficiency `η = 0.1`: with
+/// the accretion rate `ṁ` in Eddington units, the thin disk temperature scale
+/// is `T_*⁴ = FLUX_CONSTANT · ṁ / r_s`.
+const FLUX_CONSTANT: f64 = 4.4802e34;
+
+/// Kerr mass in r_s.
+const M: f64 = 0.5;
+
+/// Number of entries in the flux lookup table.
+pub const FLUX_LUT_SIZE: usize = 256;
+
+/// How the disk emission is coloured.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EmissionMode {
+    /// Radial lookup into the colour map texture.
+    ColorMap,
+    /// Blackbody colour of the local temperature, from the lookup table
+    /// generated in `renderer::blackbody`.
+    Blackbody,
+}
+
+impl EmissionMode {
+    /// Value of the `u_emission_mode` uniform.
+    pub fn index(self) -> i32 {
+        match self {
+            EmissionMode::ColorMap => 0,
+            EmissionMode::Blackbody => 1,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EmissionMode::ColorMap => "color_map",
+            EmissionMode::Blackbody => "blackbody",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            EmissionMode::ColorMap => EmissionMode::Blackbody,
+            EmissionMode::Blackbody => EmissionMode::ColorMap,
+        }
+    }
+}
+
+impl FromStr for EmissionMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "color_map" => Ok(EmissionMode::ColorMap),
+            "blackbody" => Ok(EmissionMode::Blackbody),
+            _ => Err(()),
+        }
+    }
+}
+
+/// One tunable accretion disk parameter, in the order Tab cycles through them.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DiskParam {
+    Inner,
+    Outer,
+    Height,
+    DensityV,
+    DensityH,
+    NoiseScale,
+    Speed,
+    DensityScale,
+    Absorption,
+    LogAccretionRate,
+}
+
+impl DiskParam {
+    pub const ALL: [DiskParam; 10] = [
+        DiskParam::Inner,
+        DiskParam::Outer,
+        DiskParam::Height,
+        DiskParam::DensityV,
+        DiskParam::DensityH,
+        DiskParam::NoiseScale,
+        DiskParam::Speed,
+        DiskParam::DensityScale,
+        DiskParam::Absorption,
+        DiskParam::LogAccretionRate,
+    ];
+
+    /// Key of the parameter in the `[disk]` section of a scene file.
+    pub fn name(self) -> &'static str {
+        match self {
+            DiskParam::Inner => "inner",
+            DiskParam::Outer => "outer",
+            DiskParam::Height => "height",
+            DiskParam::DensityV => "density_v",
+            DiskParam::DensityH => "density_h",
+            DiskParam::NoiseScale => "noise_scale",
+            DiskParam::Speed => "speed",
+            DiskParam::DensityScale => "density_scale",
+            DiskParam::Absorption => "absorption",
+            DiskParam::LogAccretionRate => "log_accretion_rate",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&p| p == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// `(min, max, step)` used by the controls and when loading scenes.
+    pub fn range(self) -> (f32, f32, f32) {
+        match self {
+            DiskParam::Inner => (1.0, 10.0, 0.1),
+            DiskParam::Outer => (3.0, 40.0, 0.5),
+            DiskParam::Height => (0.05, 4.0, 0.05),
+            DiskParam::DensityV => (0.0, 8.0, 0.25),
+            DiskParam::DensityH => (0.0, 4.0, 0.1),
+            DiskParam::NoiseScale => (0.0, 4.0, 0.1),
+            DiskParam::Speed => (0.0, 4.0, 0.1),
+            DiskParam::DensityScale => (0.0, 1000.0, 10.0),
+            DiskParam::Absorption => (0.0, 0.2, 0.005),
+            DiskParam::LogAccretionRate => (-12.0, 1.0, 0.25),
+        }
+    }
+}
+
+/// Geometry and appearance of the volumetric accretion disk, uploaded as the
+/// `u_disk_*` uniforms of `blackhole.frag`. Lengths are in r_s.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DiskParams {
+    /// Start the disk at the ISCO of the current spin rather than at `inner`.
+    pub isco: bool,
+    /// Inner edge used when `isco` is off.
+    pub inner: f32,
+    pub outer: f32,
+    /// Half thickness.
+    pub height: f32,
+    /// Vertical density falloff exponent; lower values look thicker.
+    pub density_v: f32,
+    /// Radial density falloff exponent.
+    pub density_h: f32,
+    pub noise_scale: f32,
+    /// Rotation speed of the noise pattern.
+    pub speed: f32,
+    /// Overall emission/density multiplier.
+    pub density_scale: f32,
+    /// Absorption coefficient used for the Beer–Lambert attenuation.
+    pub absorption: f32,
+    /// `log10` of the accretion rate in units of the Eddington rate; sets the
+    /// temperature of the blackbody emission together with the mass.
+    pub log_accretion_rate: f32,
+}
+
+impl Default for DiskParams {
+    fn default() -> Self {
+        Self {
+            isco: true,
+            inner: 2.6,
+            outer: 12.0,
+            height: 1.0,
+            density_v: 2.0,
+            density_h: 1.0,
+            noise_scale: 1.0,
+            speed: 0.5,
+            density_scale: 160.0,
+            absorption: 0.02,
+            log_accretion_rate: -7.0,
+        }
+    }
+}
+
+impl DiskParams {
+    pub fn get(&self, param: DiskParam) -> f32 {
+        match param {
+            DiskParam::Inner => self.inner,
+            DiskParam::Outer => self.outer,
+            DiskParam::Height => self.height,
+            DiskParam::DensityV => self.density_v,
+            DiskParam::DensityH => self.density_h,
+            DiskParam::NoiseScale => self.noise_scale,
+            DiskParam::Speed => self.speed,
+            DiskParam::DensityScale => self.density_scale,
+            DiskParam::Absorption => self.absorption,
+            DiskParam::LogAccretionRate => self.log_accretion_rate,
+        }
+    }
+
+    pub fn get_mut(&mut self, param: DiskParam) -> &mut f32 {
+        match param {
+            DiskParam::Inner => &mut self.inner,
+            DiskParam::Outer => &mut self.outer,
+            DiskParam::Height => &mut self.height,
+            DiskParam::DensityV => &mut self.density_v,
+            DiskParam::DensityH => &mut self.density_h,
+            DiskParam::NoiseScale => &mut self.noise_scale,
+            DiskParam::Speed => &mut self.speed,
+            DiskParam::DensityScale => &mut self.density_scale,
+            DiskParam::Absorption => &mut self.absorption,
+            DiskParam::LogAccretionRate => &mut self.log_accretion_rate,
+        }
+    }
+
+    /// Move `param` by `steps` increments of its step size, keeping it in range.
+    pub fn adjust(&mut self, param: DiskParam, steps: f32) {
+        let (_, _, step) = param.range();
+        *self.get_mut(param) += steps * step;
+        self.clamp();
+    }
+
+    /// Inner edge in r_s for spin `a/M`.
+    pub fn inner_radius(&self, spin: f64) -> f64 {
+        if self.isco {
+            isco_radius(spin)
+        } else {
+            self.inner as f64
+        }
+    }
+
+    /// Temperature scale `T_*` in kelvin for a hole whose Schwarzschild
+    /// radius is `rs_meters`: the disk at radius `r` has `T⁴ = T_*⁴ r⁻³ Q(r)`.
+    pub fn temperature_scale(&self, rs_meters: f64) -> f64 {
+        (FLUX_CONSTANT * 10f64.powf(self.log_accretion_rate as f64) / rs_meters).powf(0.25)
+    }
+
+    /// Pull every parameter into its range and keep the outer edge outside
+    /// the inner one.
+    pub fn clamp(&mut self) {
+        for param in DiskParam::ALL {
+            let (min, max, _) = param.range();
+            let value = self.get_mut(param);
+            *value = value.clamp(min, max);
+        }
+        self.outer = self.outer.max(self.inner + DiskParam::Outer.range().2);
+    }
+}
+
+/// Radius in r_s of the innermost stable circular orbit for spin `a/M`
+/// (Bardeen, Press & Teukolsky 1972). The disk always turns in the positive
+/// sense about +y, so a negative spin gives the larger retrograde ISCO.
+pub fn isco_radius(spin: f64) -> f64 {
+    let z1 = 1.0 + (1.0 - spin * spin).cbrt() * ((1.0 + spin).cbrt() + (1.0 - spin).cbrt());
+    let z2 = (3.0 * spin * spin + z1 * z1).sqrt();
+    M * (3.0 + z2 - spin.signum() * ((3.0 - z1) * (3.0 + z1 + 2.0 * z2)).max(0.0).sqrt())
+}
+
+/// Relativistic correction `Q` of the Novikov–Thorne flux
+/// `F = 3GMṀ/(8πr³) · Q(r)` at radius `r` in r_s, in the closed form of
+/// Page & Thorne (1974). It vanishes at the ISCO, where the disk exerts no
+/// torque, and like the Newtonian `1 - sqrt(r_isco/r)` tends to 1 far out.
+pub fn novikov_thorne_factor(r: f64, spin: f64) -> f64 {
+    let x = (r / M).sqrt();
+    let x0 = (isco_radius(spin) / M).sqrt();
+    if x <= x0 {
+        return 0.0;
+    }
+
+    // roots of x³ - 3x + 2a = 0
+    let angle = spin.acos() / 3.0;
+    let roots = [
+        2.0 * (angle - std::f64::consts::FRAC_PI_3).cos(),
+        2.0 * (angle + std::f64::consts::FRAC_PI_3).cos(),
+        -2.0 * angle.cos(),
+    ];
+    let mut bracket = x - x0 - 1.5 * spin * (x / x0).ln();
+    for (i, &xi) in roots.iter().enumerate() {
+        // the root that goes to 0 with the spin has a vanishing coefficient
+        if xi.abs() < 1e-9 {
+            continue;
+        }
+        let (xj, xk) = (roots[(i + 1) % 3], roots[(i + 2) % 3]);
+        let coefficient = 3.0 * (xi - spin).powi(2) / (xi * (xi - xj) * (xi - xk));
+        bracket -= coefficient * ((x - xi) / (x0 - xi)).ln();
+    }
+    (x * x * bracket / (x * x * x - 3.0 * x + 2.0 * spin)).max(0.0)
+}
+
+/// Radial Novikov–Thorne flux of the disk for one spin, tabulated for
+/// `blackhole.frag`, which reads the local temperature and brightness of the
+/// blackbody emission from it.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FluxProfile {
+    /// Radii in r_s of the first and last table entry: the ISCO and the
+    /// outer edge of the disk.
+    pub range: (f64, f64),
+    /// `F / F_peak` at radii spaced evenly in `ln r` over `range`.
+    pub table: [f32; FLUX_LUT_SIZE],
+    /// Largest `r⁻³ Q(r)` over the table, where the disk is hottest.
+    pub peak: f64,
+}
+
+impl FluxProfile {
+    pub fn new(spin: f64, outer: f64) -> Self {
+        let isco = isco_radius(spin);
+        // keep the table usable while the outer edge is inside the ISCO
+        let range = (isco, outer.max(isco + 1.0));
+        let ratio = range.1 / range.0;
+        let flux: Vec<f64> = (0..FLUX_LUT_SIZE)
+            .map(|i| {
+                let r = range.0 * ratio.powf(i as f64 / (FLUX_LUT_SIZE - 1) as f64);
+                novikov_thorne_factor(r, spin) / (r * r * r)
+            })
+            .collect();
+        let peak = flux.iter().cloned().fold(0.0, f64::max);
+
+        let mut table = [0.0; FLUX_LUT_SIZE];
+        for (entry, f) in table.iter_mut().zip(&flux) {
+            *entry = (f / peak) as f32;
+        }
+        Self { range, table, peak }
+    }
+
+    /// Temperature in kelvin of the hottest ring for the scale `T_*` of
+    /// `DiskParams::temperature_scale`.
+    pub fn peak_temperature(&self, t_star: f64) -> f64 {
+        t_star * self.peak.powf(0.25)
+    }
+
+    /// Upload `table` into `texture` as a `FLUX_LUT_SIZE`×1 float texture.
+    pub fn upload(&self, texture: GLuint) {
+        unsafe {
+            BindTexture(TEXTURE_2D, texture);
+            TexImage2D(
+                TEXTURE_2D,
+                0,
+                R32F as i32,
+                FLUX_LUT_SIZE as i32,
+                1,
+                0,
+                RED,
+                FLOAT,
+                self.table.as_ptr() as *const _,
+            );
+        }
+    }
+}
+
+/// Texture for `FluxProfile::upload`, with linear filtering.
+pub fn create_flux_texture() -> GLuint {
+    let mut texture_id: GLuint = 0;
+    unsafe {
+        GenTextures(1, &mut texture_id);
+        BindTexture(TEXTURE_2D, texture_id);
+        TexParameteri(TEXTURE_2D, TEXTURE_MIN_FILTER, LINEAR as i32);
+        TexParameteri(TEXTURE_2D, TEXTURE_MAG_FILTER, LINEAR as i32);
+        TexParameteri(TEXTURE_2D, TEXTURE_WRAP_S, CLAMP_TO_EDGE as i32);
+        TexParameteri(TEXTURE_2D, TEXTURE_WRAP_T, CLAMP_TO_EDGE as i32);
+    }
+    texture_id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn isco_matches_known_values() {
+        assert!((isco_radius(0.0) - 3.0).abs() < 1e-12);
+        // 1.2370 M prograde and 8.9944 M retrograde
+        assert!((isco_radius(0.998) - 0.5 * 1.2370).abs() < 1e-4);
+        assert!((isco_radius(-0.998) - 0.5 * 8.9944).abs() < 1e-4);
+        assert!((isco_radius(1.0) - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn flux_vanishes_at_the_isco_and_goes_newtonian() {
+        for spin in [-0.9, 0.0, 0.5, 0.998] {
+            let isco = isco_radius(spin);
+            assert_eq!(novikov_thorne_factor(isco, spin), 0.0);
+            assert!(novikov_thorne_factor(1.01 * isco, spin) > 0.0);
+            assert!((novikov_thorne_factor(1e8, spin) - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn flux_table_is_normalised_to_its_peak() {
+        let profile = FluxProfile::new(0.7, 12.0);
+        assert_eq!(profile.range, (isco_radius(0.7), 12.0));
+        assert_eq!(profile.table[0], 0.0);
+        assert_eq!(profile.table.iter().cloned().fold(0.0, f32::max), 1.0);
+    }
+}
@@ -0,0 +1,337 @@
+use std::str::FromStr;
+
+use gl::types::GLuint;
+
+use crate::gl_bindings::*;
+use crate::renderer::framebuffer::RenderTarget;
+
+/// White point of the extended Reinhard curve: radiance that maps to 1.
+pub const WHITE_POINT: f64 = 4.0;
+/// Linear white of the Uncharted 2 curve.
+const UNCHARTED2_WHITE: f64 = 11.2;
+/// Display value the automatic exposure gives the log-average luminance.
+pub const KEY_VALUE: f64 = 0.18;
+/// Range of the automatic exposure in EV, so a black or blinding view does not
+/// push it to extremes.
+pub const AUTO_EV_RANGE: f32 = 8.0;
+/// Time constant in seconds with which the automatic exposure follows the view.
+const ADAPTATION_TIME: f32 = 0.5;
+/// Width in pixels of the copy of the frame the exposure meter reads.
+const METER_WIDTH: u32 = 64;
+
+/// Curve mapping exposed linear radiance to display values before gamma.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Tonemap {
+    /// `c / (1 + c)`, the curve the renderer has always used.
+    Reinhard,
+    /// Reinhard with `WHITE_POINT` mapped to 1 instead of infinity.
+    ReinhardExtended,
+    /// Narkowicz's fit of the ACES filmic reference transform.
+    Aces,
+    /// John Hable's filmic curve from Uncharted 2.
+    Uncharted2,
+    /// No curve; everything above 1 clips.
+    Linear,
+}
+
+impl Tonemap {
+    /// Value of the `u_tonemap` uniform.
+    pub fn index(self) -> i32 {
+        match self {
+            Tonemap::Reinhard => 0,
+            Tonemap::ReinhardExtended => 1,
+            Tonemap::Aces => 2,
+            Tonemap::Uncharted2 => 3,
+            Tonemap::Linear => 4,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tonemap::Reinhard => "reinhard",
+            Tonemap::ReinhardExtended => "reinhard_extended",
+            Tonemap::Aces => "aces",
+            Tonemap::Uncharted2 => "uncharted2",
+            Tonemap::Linear => "linear",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Tonemap::Reinhard => Tonemap::ReinhardExtended,
+            Tonemap::ReinhardExtended => Tonemap::Aces,
+            Tonemap::Aces => Tonemap::Uncharted2,
+            Tonemap::Uncharted2 => Tonemap::Linear,
+            Tonemap::Linear => Tonemap::Reinhard,
+        }
+    }
+
+    /// The curve applied to one colour channel, as `tonemap` in `post.frag`.
+    pub fn apply(self, c: f64) -> f64 {
+        match self {
+            Tonemap::Reinhard => c / (c + 1.0),
+            Tonemap::ReinhardExtended => c * (1.0 + c / (WHITE_POINT * WHITE_POINT)) / (1.0 + c),
+            Tonemap::Aces => (c * (2.51 * c + 0.03) / (c * (2.43 * c + 0.59) + 0.14)).clamp(0.0, 1.0),
+            Tonemap::Uncharted2 => uncharted2_curve(2.0 * c) / uncharted2_curve(UNCHARTED2_WHITE),
+            Tonemap::Linear => c.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl FromStr for Tonemap {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "reinhard" => Ok(Tonemap::Reinhard),
+            "reinhard_extended" => Ok(Tonemap::ReinhardExtended),
+            "aces" => Ok(Tonemap::Aces),
+            "uncharted2" => Ok(Tonemap::Uncharted2),
+            "linear" => Ok(Tonemap::Linear),
+            _ => Err(()),
+        }
+    }
+}
+
+fn uncharted2_curve(x: f64) -> f64 {
+    const A: f64 = 0.15;
+    const B: f64 = 0.50;
+    const C: f64 = 0.10;
+    const D: f64 = 0.20;
+    const E: f64 = 0.02;
+    const F: f64 = 0.30;
+    (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+}
+
+/// How the linear radiance of a frame is turned into display values.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Exposure {
+    pub tonemap: Tonemap,
+    /// Exposure in EV: radiance is scaled by `2^ev` before the curve. With
+    /// `auto` it is a compensation on top of the metered exposure.
+    pub ev: f32,
+    /// Expose for the log-average luminance of the view.
+    pub auto: bool,
+}
+
+impl Default for Exposure {
+    fn default() -> Self {
+        Self { tonemap: Tonemap::Reinhard, ev: 0.0, auto: false }
+    }
+}
+
+/// Luminance of a linear sRGB colour.
+pub fn luminance(rgb: [f64; 3]) -> f64 {
+    0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2]
+}
+
+/// Exposure in EV that maps the log-average luminance of `pixels` to
+/// `KEY_VALUE`, limited to `AUTO_EV_RANGE`.
+pub fn auto_exposure<I: IntoIterator<Item = [f64; 3]>>(pixels: I) -> f32 {
+    let (mut sum, mut count) = (0.0, 0);
+    for rgb in pixels {
+        // the offset keeps black pixels (the shadow) from sending the log to -inf
+        sum += (1e-4 + luminance(rgb)).ln();
+        count += 1;
+    }
+    if count == 0 {
+        return 0.0;
+    }
+    let log_average = (sum / count as f64).exp();
+    ((KEY_VALUE / log_average).log2() as f32).clamp(-AUTO_EV_RANGE, AUTO_EV_RANGE)
+}
+
+/// Automatic exposure for the GPU renderer: the HDR scene is scaled down to a
+/// small float target and read back, and the exposure eases towards what the
+/// frame calls for. In the window the read goes through a pixel buffer and is
+/// picked up on a later frame, so metering never waits for the GPU.
+pub struct ExposureMeter {
+    /// Small float copy of the scene, sized by `resize`.
+    target: Option<RenderTarget>,
+    /// Pixel buffer that `update` reads the copy into, created on first use.
+    buffer: GLuint,
+    /// Fence and size of the read into `buffer` that is still in flight.
+    pending: Option<(types::GLsync, u32, u32)>,
+    /// Seconds since the exposure last adapted.
+    elapsed: f32,
+    /// Metered exposure in EV, added to `Exposure::ev`.
+    pub ev: f32,
+}
+
+impl ExposureMeter {
+    pub fn new() -> Self {
+        Self { target: None, buffer: 0, pending: None, elapsed: 0.0, ev: 0.0 }
+    }
+
+    /// Make `target` about `METER_WIDTH` pixels wide with the aspect of a
+    /// `width`×`height` view, reusing it while the aspect stays the same.
+    fn resize(&mut self, width: u32, height: u32) -> Result<(), String> {
+        let height = (METER_WIDTH * height / width.max(1)).max(1);
+        if !self.target.as_ref().is_some_and(|t| t.width == METER_WIDTH && t.height == height) {
+            self.target = Some(RenderTarget::hdr(METER_WIDTH, height)?);
+        }
+        Ok(())
+    }
+
+    /// Scale `scene` down into `target` and return the target.
+    fn downsample(&mut self, scene: &RenderTarget) -> Result<&RenderTarget, String> {
+        self.resize(scene.width, scene.height)?;
+        let target = self.target.as_ref().unwrap();
+        unsafe {
+            BindFramebuffer(READ_FRAMEBUFFER, scene.fbo);
+            BindFramebuffer(DRAW_FRAMEBUFFER, target.fbo);
+            BlitFramebuffer(
+                0,
+                0,
+                scene.width as i32,
+                scene.height as i32,
+                0,
+                0,
+                target.width as i32,
+                target.height as i32,
+                COLOR_BUFFER_BIT,
+                LINEAR,
+            );
+        }
+        Ok(target)
+    }
+
+    /// Meter the linear radiance in `scene` and jump straight to its
+    /// exposure, waiting for the read. For single renders, which have no
+    /// earlier frame to adapt from.
+    pub fn meter(&mut self, scene: &RenderTarget) -> Result<(), String> {
+        let pixels = self.downsample(scene)?.read_pixels_f32();
+        self.ev = auto_exposure(pixels.chunks_exact(4).map(|p| [p[0] as f64, p[1] as f64, p[2] as f64]));
+        Ok(())
+    }
+
+    /// Adapt to the last frame whose read has arrived, over the `dt` seconds
+    /// of every frame since the previous one did, and start reading `scene`
+    /// if no read is in flight. The exposure thus lags the view by a frame
+    /// or so, which the adaptation hides.
+    pub fn update(&mut self, scene: &RenderTarget, dt: f32) -> Result<(), String> {
+        self.elapsed += dt;
+        if let Some((sync, width, height)) = self.pending {
+            let status = unsafe { ClientWaitSync(sync, 0, 0) };
+            if status == TIMEOUT_EXPIRED {
+                return Ok(());
+            }
+            unsafe {
+                DeleteSync(sync);
+            }
+            self.pending = None;
+            if status == ALREADY_SIGNALED || status == CONDITION_SATISFIED {
+                let metered = self.read_buffer(width, height);
+                self.ev += (metered - self.ev) * (1.0 - (-self.elapsed / ADAPTATION_TIME).exp());
+                self.elapsed = 0.0;
+            }
+        }
+
+        if self.buffer == 0 {
+            unsafe {
+                GenBuffers(1, &mut self.buffer);
+            }
+        }
+        let buffer = self.buffer;
+        let target = self.downsample(scene)?;
+        let (width, height) = (target.width, target.height);
+        unsafe {
+            BindBuffer(PIXEL_PACK_BUFFER, buffer);
+            BufferData(PIXEL_PACK_BUFFER, pixel_bytes(width, height), std::ptr::null(), STREAM_READ);
+            target.bind();
+            PixelStorei(PACK_ALIGNMENT, 1);
+            // into the bound buffer, so this returns without waiting
+            ReadPixels(0, 0, width as i32, height as i32, RGBA, FLOAT, std::ptr::null_mut());
+            target.unbind();
+            BindBuffer(PIXEL_PACK_BUFFER, 0);
+            self.pending = Some((FenceSync(SYNC_GPU_COMMANDS_COMPLETE, 0), width, height));
+        }
+        Ok(())
+    }
+
+    /// Metered exposure of the `width`×`height` frame that has arrived in
+    /// `buffer`.
+    fn read_buffer(&self, width: u32, height: u32) -> f32 {
+        unsafe {
+            BindBuffer(PIXEL_PACK_BUFFER, self.buffer);
+            let data = MapBufferRange(PIXEL_PACK_BUFFER, 0, pixel_bytes(width, height), MAP_READ_BIT) as *const f32;
+            let metered = if data.is_null() {
+                self.ev
+            } else {
+                let pixels = std::slice::from_raw_parts(data, (width * height * 4) as usize);
+                auto_exposure(pixels.chunks_exact(4).map(|p| [p[0] as f64, p[1] as f64, p[2] as f64]))
+            };
+            UnmapBuffer(PIXEL_PACK_BUFFER);
+            BindBuffer(PIXEL_PACK_BUFFER, 0);
+            metered
+        }
+    }
+}
+
+impl Drop for ExposureMeter {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some((sync, _, _)) = self.pending {
+                DeleteSync(sync);
+            }
+            if self.buffer != 0 {
+                DeleteBuffers(1, &self.buffer);
+            }
+        }
+    }
+}
+
+/// Size in bytes of a `width`×`height` RGBA float image.
+fn pixel_bytes(width: u32, height: u32) -> isize {
+    (width * height * 4) as isize * std::mem::size_of::<f32>() as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operators() -> Vec<Tonemap> {
+        let mut all = vec![Tonemap::Reinhard];
+        while all.last().unwrap().next() != Tonemap::Reinhard {
+            all.push(all.last().unwrap().next());
+        }
+        all
+    }
+
+    #[test]
+    fn operators_are_monotonic_from_zero() {
+        assert_eq!(operators().len(), 5);
+        for tonemap in operators() {
+            assert!(tonemap.apply(0.0).abs() < 1e-12, "{}", tonemap.name());
+            let mut previous = 0.0;
+            for i in 1..=4000 {
+                let value = tonemap.apply(i as f64 * 0.01);
+                assert!(value >= previous, "{} at {}", tonemap.name(), i as f64 * 0.01);
+                previous = value;
+            }
+        }
+    }
+
+    #[test]
+    fn reinhard_and_aces_stay_below_white() {
+        for c in [0.5, 1.0, 10.0, 1e3, 1e6] {
+            assert!(Tonemap::Reinhard.apply(c) < 1.0);
+            assert!(Tonemap::Aces.apply(c) <= 1.0);
+        }
+    }
+
+    #[test]
+    fn auto_exposure_leaves_mid_grey_alone() {
+        let ev = auto_exposure(std::iter::repeat_n([KEY_VALUE; 3], 100));
+        assert!(ev.abs() < 0.01, "{}", ev);
+        // twice as bright needs one stop less
+        let ev = auto_exposure(std::iter::repeat_n([2.0 * KEY_VALUE; 3], 100));
+        assert!((ev + 1.0).abs() < 0.01, "{}", ev);
+    }
+
+    #[test]
+    fn auto_exposure_of_empty_and_black_frames_is_finite() {
+        assert_eq!(auto_exposure(std::iter::empty()), 0.0);
+        assert_eq!(auto_exposure(std::iter::repeat_n([0.0; 3], 100)), AUTO_EV_RANGE);
+    }
+}
@@ -0,0 +1,113 @@
+use std::str::FromStr;
+
+use nalgebra_glm as glm;
+
+use crate::renderer::app::MAX_SPIN;
+use crate::renderer::disk::{DiskParams, EmissionMode, FluxProfile};
+use crate::renderer::exposure::Exposure;
+use crate::renderer::post::Bloom;
+use crate::scene::Scene;
+
+/// Presets for the dynamic quality uniforms, trading frame time for fewer
+/// integration and sampling artifacts.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Quality {
+    Low,
+    Medium,
+    High,
+    Ultra,
+}
+
+impl Quality {
+    /// `(u_max_iter, u_step_scale, u_noise_lod)` for this preset.
+    pub fn settings(self) -> (i32, f32, i32) {
+        match self {
+            Quality::Low => (300, 1.6, 1),
+            Quality::Medium => (600, 1.0, 2),
+            Quality::High => (1200, 0.6, 4),
+            Quality::Ultra => (2000, 0.35, 6),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Quality::Low => "low",
+            Quality::Medium => "medium",
+            Quality::High => "high",
+            Quality::Ultra => "ultra",
+        }
+    }
+}
+
+impl FromStr for Quality {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "low" => Ok(Quality::Low),
+            "medium" => Ok(Quality::Medium),
+            "high" => Ok(Quality::High),
+            "ultra" => Ok(Quality::Ultra),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Everything the shaders read from their uniforms to draw one frame, so
+/// the GPU and CPU renderers are driven from the same values.
+#[derive(Clone, Copy)]
+pub struct FrameParams {
+    pub width: u32,
+    pub height: u32,
+    pub time: f32,
+    pub camera_pos: glm::Vec3,
+    /// Camera right/up/forward vectors as rows, as `Camera::get_view_matrix` returns them.
+    pub view_matrix: glm::Mat3,
+    pub fov: f32,
+    pub render_disk: bool,
+    pub gravitational_lensing: bool,
+    /// Doppler beaming and gravitational redshift of the disk emission.
+    pub redshift: bool,
+    pub emission_mode: EmissionMode,
+    pub spin: f32,
+    /// Physical size of `r_s` in metres, from the black hole's mass.
+    pub rs_meters: f32,
+    pub max_iter: i32,
+    pub step_scale: f32,
+    pub noise_lod: i32,
+    pub disk: DiskParams,
+    /// Disk flux for `spin` and `disk.outer`.
+    pub flux: FluxProfile,
+    pub exposure: Exposure,
+    pub bloom: Bloom,
+}
+
+impl FrameParams {
+    /// A `width`×`height` frame of a scene, without needing a window.
+    pub fn from_scene(scene: &Scene, width: u32, height: u32) -> Self {
+        let camera = scene.camera();
+        let (max_iter, step_scale, noise_lod) = scene.quality.settings();
+        let spin = scene.black_hole.spin.clamp(-MAX_SPIN, MAX_SPIN);
+        Self {
+            width,
+            height,
+            time: scene.time,
+            camera_pos: camera.get_position(),
+            view_matrix: camera.get_view_matrix(),
+            fov: scene.fov,
+            render_disk: scene.render_disk,
+            gravitational_lensing: scene.gravitational_lensing,
+            redshift: scene.redshift,
+            emission_mode: scene.emission_mode,
+            spin: spin as f32,
+            rs_meters: scene.black_hole.schwarzschild_radius() as f32,
+            max_iter,
+            step_scale,
+            noise_lod,
+            disk: scene.disk,
+            flux: FluxProfile::new(spin, scene.disk.outer as f64),
+            exposure: scene.exposure,
+            bloom: scene.bloom,
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::gl_bindings::*;
+use gl::types::{GLenum, GLuint};
+
+/// Offscreen colour target, so frames can be rendered at any size
+/// independently of the window.
+pub struct RenderTarget {
+    pub fbo: GLuint,
+    pub texture: GLuint,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RenderTarget {
+    pub fn new(width: u32, height: u32) -> Result<Self, String> {
+        Self::with_format(width, height, RGBA8, UNSIGNED_BYTE)
+    }
+
+    /// Target with 32-bit float channels, which keeps radiance above 1.
+    pub fn hdr(width: u32, height: u32) -> Result<Self, String> {
+        Self::with_format(width, height, RGBA32F, FLOAT)
+    }
+
+    fn with_format(width: u32, height: u32, internal_format: GLenum, pixel_type: GLenum) -> Result<Self, String> {
+        let mut max_size = 0;
+        let mut max_viewport = [0; 2];
+        unsafe {
+            GetIntegerv(MAX_TEXTURE_SIZE, &mut max_size);
+            GetIntegerv(MAX_VIEWPORT_DIMS, max_viewport.as_mut_ptr());
+        }
+        let max_width = max_size.min(max_viewport[0]) as u32;
+        let max_height = max_size.min(max_viewport[1]) as u32;
+        if width > max_width || height > max_height {
+            return Err(format!(
+                "{}x{} exceeds the largest render target this GPU supports ({}x{})",
+                width, height, max_width, max_height
+            ));
+        }
+
+        let mut fbo: GLuint = 0;
+        let mut texture: GLuint = 0;
+        unsafe {
+            GenTextures(1, &mut texture);
+            BindTexture(TEXTURE_2D, texture);
+            TexImage2D(
+                TEXTURE_2D,
+                0,
+                internal_format as i32,
+                width as i32,
+                height as i32,
+                0,
+                RGBA,
+                pixel_type,
+                std::ptr::null(),
+            );
+            TexParameteri(TEXTURE_2D, TEXTURE_MIN_FILTER, LINEAR as i32);
+            TexParameteri(TEXTURE_2D, TEXTURE_MAG_FILTER, LINEAR as i32);
+            TexParameteri(TEXTURE_2D, TEXTURE_WRAP_S, CLAMP_TO_EDGE as i32);
+            TexParameteri(TEXTURE_2D, TEXTURE_WRAP_T, CLAMP_TO_EDGE as i32);
+            BindTexture(TEXTURE_2D, 0);
+
+            GenFramebuffers(1, &mut fbo);
+            BindFramebuffer(FRAMEBUFFER, fbo);
+            FramebufferTexture2D(FRAMEBUFFER, COLOR_ATTACHMENT0, TEXTURE_2D, texture, 0);
+            let status = CheckFramebufferStatus(FRAMEBUFFER);
+            BindFramebuffer(FRAMEBUFFER, 0);
+
+            if status != FRAMEBUFFER_COMPLETE {
+                DeleteFramebuffers(1, &fbo);
+                DeleteTextures(1, &texture);
+                return Err(format!("Framebuffer incomplete (status 0x{:x})", status));
+            }
+        }
+
+        Ok(Self { fbo, texture, width, height })
+    }
+
+    /// Direct drawing into this target and cover it with the viewport.
+    pub fn bind(&self) {
+        unsafe {
+            BindFramebuffer(FRAMEBUFFER, self.fbo);
+            Viewport(0, 0, self.width as i32, self.height as i32);
+        }
+    }
+
+    /// Return to drawing into the window.
+    pub fn unbind(&self) {
+        unsafe {
+            BindFramebuffer(FRAMEBUFFER, 0);
+        }
+    }
+
+    /// Contents of the target as top-to-bottom RGBA rows.
+    pub fn read_pixels(&self) -> Vec<u8> {
+        self.bind();
+        let pixels = read_rgba(self.width, self.height);
+        self.unbind();
+        pixels
+    }
+
+    /// Contents of an `hdr` target as top-to-bottom RGBA rows.
+    pub fn read_pixels_f32(&self) -> Vec<f32> {
+        self.bind();
+        let pixels = read_rgba_f32(self.width, self.height);
+        self.unbind();
+        pixels
+    }
+}
+
+impl Drop for RenderTarget {
+    fn drop(&mut self) {
+        unsafe {
+            DeleteFramebuffers(1, &self.fbo);
+            DeleteTextures(1, &self.texture);
+        }
+    }
+}
+
+/// Read the bound framebuffer as top-to-bottom RGBA rows (OpenGL returns
+/// them bottom-up).
+pub fn read_rgba(width: u32, height: u32) -> Vec<u8> {
+    read_pixels(width, height, UNSIGNED_BYTE)
+}
+
+/// `read_rgba` with float channels, for float targets.
+pub fn read_rgba_f32(width: u32, height: u32) -> Vec<f32> {
+    read_pixels(width, height, FLOAT)
+}
+
+fn read_pixels<T: Copy + Default>(width: u32, height: u32, pixel_type: GLenum) -> Vec<T> {
+    let row = (width * 4) as usize;
+    let mut pixels = vec![T::default(); row * height as usize];
+    unsafe {
+        PixelStorei(PACK_ALIGNMENT, 1);
+        ReadPixels(
+            0,
+            0,
+            width as i32,
+            height as i32,
+            RGBA,
+            pixel_type,
+            pixels.as_mut_ptr() as *mut std::ffi::c_void,
+        );
+    }
+
+    let mut flipped = vec![T::default(); pixels.len()];
+    for (dst, src) in flipped.chunks_exact_mut(row).zip(pixels.chunks_exact(row).rev()) {
+        dst.copy_from_slice(src);
+    }
+    flipped
+}
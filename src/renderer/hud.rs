@@ -0,0 +1,89 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::fps::{FpsCounter, HISTORY};
+use crate::renderer::overlay::{Color, Overlay};
+
+/// How long a message stays up, fading out over the last `FADE_TIME`.
+const MESSAGE_TIME: Duration = Duration::from_secs(4);
+const FADE_TIME: f32 = 1.0;
+/// Messages shown at once; older ones go early to make room.
+const MAX_MESSAGES: usize = 6;
+/// Width of a frame's bar in the frame time graph, in pixels.
+const BAR_WIDTH: f32 = 2.0;
+const GRAPH_HEIGHT: f32 = 48.0;
+/// Frame time of the reference line in the graph, in seconds.
+const TARGET_FRAME_TIME: f32 = 1.0 / 60.0;
+const PANEL: Color = [0.0, 0.0, 0.0, 0.6];
+const TEXT: Color = [1.0, 1.0, 1.0, 1.0];
+
+/// Heads-up display over the frame: a readout of the view with a graph of
+/// recent frame times in the top right corner, and short-lived messages
+/// about what the keys did in the bottom right.
+pub struct Hud {
+    pub visible: bool,
+    /// Messages with the time they were posted, oldest first.
+    messages: VecDeque<(String, Instant)>,
+}
+
+impl Hud {
+    pub fn new() -> Self {
+        Self { visible: true, messages: VecDeque::new() }
+    }
+
+    /// Show `message` for a few seconds.
+    pub fn message(&mut self, message: String) {
+        if self.messages.len() == MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+        self.messages.push_back((message, Instant::now()));
+    }
+
+    /// Queue the HUD for a `width`×`height` viewport on `overlay`, with the
+    /// lines of `readout` above the frame times of `fps`.
+    pub fn draw(&mut self, overlay: &mut Overlay, readout: &str, fps: &FpsCounter, width: u32, height: u32) {
+        self.messages.retain(|(_, posted)| posted.elapsed() < MESSAGE_TIME);
+        if !self.visible {
+            return;
+        }
+        let (width, height) = (width as f32, height as f32);
+        let margin = overlay.cell_width;
+
+        // readout and graph
+        let graph_width = HISTORY as f32 * BAR_WIDTH;
+        let (text_width, text_height) = overlay.measure(readout);
+        let panel_width = text_width.max(graph_width) + 2.0 * margin;
+        let left = width - margin - panel_width;
+        overlay.rect(left, margin, panel_width, text_height + GRAPH_HEIGHT + 3.0 * margin, PANEL);
+        overlay.text(readout, left + margin, 2.0 * margin, TEXT);
+
+        let bottom = 3.0 * margin + text_height + GRAPH_HEIGHT;
+        let scale = fps.frame_times.iter().fold(2.0 * TARGET_FRAME_TIME, |max, &t| max.max(t));
+        for (i, &time) in fps.frame_times.iter().enumerate() {
+            let bar = (time / scale * GRAPH_HEIGHT).max(1.0);
+            let color = if time <= TARGET_FRAME_TIME * 1.05 {
+                [0.3, 0.9, 0.3, 0.9]
+            } else if time <= 2.0 * TARGET_FRAME_TIME * 1.05 {
+                [0.95, 0.8, 0.2, 0.9]
+            } else {
+                [0.95, 0.3, 0.25, 0.9]
+            };
+            overlay.rect(left + margin + i as f32 * BAR_WIDTH, bottom - bar, BAR_WIDTH, bar, color);
+        }
+        let target = bottom - TARGET_FRAME_TIME / scale * GRAPH_HEIGHT;
+        overlay.rect(left + margin, target, graph_width, 1.0, [1.0, 1.0, 1.0, 0.5]);
+
+        // messages, newest at the bottom
+        let mut y = height - margin - overlay.cell_height;
+        for (message, posted) in self.messages.iter().rev() {
+            let remaining = MESSAGE_TIME.saturating_sub(posted.elapsed()).as_secs_f32();
+            let alpha = (remaining / FADE_TIME).min(1.0);
+            let (message_width, message_height) = overlay.measure(message);
+            let x = width - 1.5 * margin - message_width;
+            y -= message_height - overlay.cell_height;
+            overlay.rect(x - margin / 2.0, y, message_width + margin, message_height, [0.0, 0.0, 0.0, 0.6 * alpha]);
+            overlay.text(message, x, y, [1.0, 1.0, 1.0, alpha]);
+            y -= overlay.cell_height + margin / 2.0;
+        }
+    }
+}
@@ -1,5 +1,16 @@
 pub mod window;
+pub mod framebuffer;
+pub mod frame;
+pub mod disk;
+pub mod blackbody;
+pub mod exposure;
+pub mod post;
+pub mod poster;
+pub mod recorder;
+pub mod cpu;
 pub mod mesh;
 pub mod app;
 pub mod utils;
-pub mod skybox;
\ No newline at end of file
+pub mod skybox;
+pub mod overlay;
+pub mod hud;
@@ -0,0 +1,255 @@
+use gl::types::GLuint;
+use image::RgbaImage;
+use nalgebra_glm as glm;
+
+use crate::gl_bindings::*;
+use crate::shader::ShaderProgram;
+
+const VERTEX_SHADER: &str = "shaders/overlay.vert";
+const FRAGMENT_SHADER: &str = "shaders/overlay.frag";
+/// Monospace glyphs of the printable ASCII characters from the space on, in
+/// a grid of `COLUMNS`×`ROWS` equal cells with the coverage in alpha.
+pub const FONT: &str = "assets/font.png";
+const COLUMNS: u32 = 16;
+const ROWS: u32 = 6;
+/// Floats per vertex: position, texture coordinates and colour.
+const VERTEX_SIZE: usize = 8;
+/// Triangles making up a circle.
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// Straight RGBA.
+pub type Color = [f32; 4];
+
+/// Draws the 2D layer over the frame: text in the bitmap font, solid shapes
+/// and images, in pixels from the top left of the viewport. Everything
+/// queued between two `flush`es goes to the GPU in one buffer, with a draw
+/// call per change of texture.
+pub struct Overlay {
+    program: ShaderProgram,
+    vao: GLuint,
+    vbo: GLuint,
+    font: GLuint,
+    /// Size of a character in pixels.
+    pub cell_width: f32,
+    pub cell_height: f32,
+    /// Vertices queued since the last `flush`.
+    vertices: Vec<f32>,
+    /// Texture and first vertex of each run of queued vertices that sample
+    /// the same texture.
+    batches: Vec<(GLuint, usize)>,
+}
+
+impl Overlay {
+    pub fn new() -> Result<Self, String> {
+        let font = image::open(FONT).map_err(|e| format!("Failed to load {}: {}", FONT, e))?.to_rgba8();
+        let (width, height) = font.dimensions();
+        let program = ShaderProgram::new(VERTEX_SHADER, FRAGMENT_SHADER)?;
+        // drawn at its own size, so the pixels stay sharp
+        let font = upload_texture(&font, false);
+
+        let (mut vao, mut vbo) = (0, 0);
+        unsafe {
+            GenVertexArrays(1, &mut vao);
+            GenBuffers(1, &mut vbo);
+            BindVertexArray(vao);
+            BindBuffer(ARRAY_BUFFER, vbo);
+            let stride = (VERTEX_SIZE * std::mem::size_of::<f32>()) as i32;
+            for (index, (size, offset)) in [(2, 0), (2, 2), (4, 4)].into_iter().enumerate() {
+                EnableVertexAttribArray(index as u32);
+                VertexAttribPointer(
+                    index as u32,
+                    size,
+                    FLOAT,
+                    FALSE,
+                    stride,
+                    (offset * std::mem::size_of::<f32>()) as *const _,
+                );
+            }
+            BindVertexArray(0);
+        }
+
+        Ok(Self {
+            program,
+            vao,
+            vbo,
+            font,
+            cell_width: (width / COLUMNS) as f32,
+            cell_height: (height / ROWS) as f32,
+            vertices: Vec::new(),
+            batches: Vec::new(),
+        })
+    }
+
+    /// Queue `text` with the top left of its first character at `(x, y)`.
+    /// Every line starts again at `x`; characters outside printable ASCII
+    /// show as `?`.
+    pub fn text(&mut self, text: &str, x: f32, y: f32, color: Color) {
+        for (row, line) in text.lines().enumerate() {
+            let top = y + row as f32 * self.cell_height;
+            for (column, c) in line.chars().enumerate() {
+                let code = if (' '..='~').contains(&c) { c as u32 } else { '?' as u32 };
+                if code == ' ' as u32 {
+                    continue;
+                }
+                let index = code - ' ' as u32;
+                let u = (index % COLUMNS) as f32 / COLUMNS as f32;
+                let v = (index / COLUMNS) as f32 / ROWS as f32;
+                let left = x + column as f32 * self.cell_width;
+                self.quad(
+                    Some(self.font),
+                    [left, top, left + self.cell_width, top + self.cell_height],
+                    [u, v, u + 1.0 / COLUMNS as f32, v + 1.0 / ROWS as f32],
+                    color,
+                );
+            }
+        }
+    }
+
+    /// Queue a solid rectangle with its top left at `(x, y)`.
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: Color) {
+        self.quad(None, [x, y, x + width, y + height], [-1.0; 4], color);
+    }
+
+    /// Queue a solid disc around `(x, y)`.
+    pub fn circle(&mut self, x: f32, y: f32, radius: f32, color: Color) {
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            (x + radius * angle.cos(), y + radius * angle.sin())
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            let (a, b) = (point(i), point(i + 1));
+            for (px, py) in [(x, y), a, b] {
+                self.vertex(None, px, py, -1.0, -1.0, color);
+            }
+        }
+    }
+
+    /// Queue the whole of `texture`, as made by `load_image`, stretched over
+    /// a rectangle with its top left at `(x, y)` and tinted by `color`.
+    pub fn image(&mut self, texture: GLuint, x: f32, y: f32, width: f32, height: f32, color: Color) {
+        self.quad(Some(texture), [x, y, x + width, y + height], [0.0, 0.0, 1.0, 1.0], color);
+    }
+
+    /// Width and height in pixels of `text` as `text` lays it out.
+    pub fn measure(&self, text: &str) -> (f32, f32) {
+        let columns = text.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+        (columns as f32 * self.cell_width, text.lines().count() as f32 * self.cell_height)
+    }
+
+    /// Draw everything queued over the contents of the bound framebuffer,
+    /// whose viewport is `width`×`height`.
+    pub fn flush(&mut self, width: u32, height: u32) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        unsafe {
+            Disable(DEPTH_TEST);
+            Enable(BLEND);
+            BlendFunc(SRC_ALPHA, ONE_MINUS_SRC_ALPHA);
+            self.program.bind();
+            self.program.set_vec2("u_viewport", glm::vec2(width as f32, height as f32));
+            ActiveTexture(TEXTURE0);
+            self.program.set_sampler("u_texture", 0);
+
+            BindVertexArray(self.vao);
+            BindBuffer(ARRAY_BUFFER, self.vbo);
+            BufferData(
+                ARRAY_BUFFER,
+                (self.vertices.len() * std::mem::size_of::<f32>()) as isize,
+                self.vertices.as_ptr() as *const _,
+                STREAM_DRAW,
+            );
+            let count = self.vertices.len() / VERTEX_SIZE;
+            for (i, &(texture, first)) in self.batches.iter().enumerate() {
+                let end = self.batches.get(i + 1).map_or(count, |&(_, next)| next);
+                BindTexture(TEXTURE_2D, texture);
+                DrawArrays(TRIANGLES, first as i32, (end - first) as i32);
+            }
+            BindVertexArray(0);
+            Disable(BLEND);
+        }
+        self.vertices.clear();
+        self.batches.clear();
+    }
+
+    /// Queue two triangles covering `rect` (left, top, right, bottom) with
+    /// the texture coordinates `uv` in the same order.
+    fn quad(&mut self, texture: Option<GLuint>, rect: [f32; 4], uv: [f32; 4], color: Color) {
+        let [left, top, right, bottom] = rect;
+        let corners = [
+            (left, top, uv[0], uv[1]),
+            (right, top, uv[2], uv[1]),
+            (right, bottom, uv[2], uv[3]),
+            (right, bottom, uv[2], uv[3]),
+            (left, bottom, uv[0], uv[3]),
+            (left, top, uv[0], uv[1]),
+        ];
+        for (x, y, u, v) in corners {
+            self.vertex(texture, x, y, u, v, color);
+        }
+    }
+
+    /// Queue one vertex. Solid shapes, with no `texture`, join whichever
+    /// batch is open.
+    fn vertex(&mut self, texture: Option<GLuint>, x: f32, y: f32, u: f32, v: f32, color: Color) {
+        let first = self.vertices.len() / VERTEX_SIZE;
+        match (texture, self.batches.last()) {
+            (Some(texture), Some(&(last, _))) if texture != last => self.batches.push((texture, first)),
+            (_, None) => self.batches.push((texture.unwrap_or(self.font), first)),
+            _ => {}
+        }
+        self.vertices.extend_from_slice(&[x, y, u, v]);
+        self.vertices.extend_from_slice(&color);
+    }
+}
+
+impl Drop for Overlay {
+    fn drop(&mut self) {
+        unsafe {
+            DeleteVertexArrays(1, &self.vao);
+            DeleteBuffers(1, &self.vbo);
+            DeleteTextures(1, &self.font);
+        }
+    }
+}
+
+/// Load the image at `path` as a texture for `Overlay::image`, keeping its
+/// alpha and with mipmaps for drawing it smaller.
+pub fn load_image(path: &str) -> Result<GLuint, String> {
+    let image = image::open(path).map_err(|e| format!("Failed to load {}: {}", path, e))?.to_rgba8();
+    Ok(upload_texture(&image, true))
+}
+
+/// `image` as a texture with its first row at the top, filtered linearly
+/// with mipmaps if `smooth` and else sampled at the nearest texel.
+fn upload_texture(image: &RgbaImage, smooth: bool) -> GLuint {
+    let (width, height) = image.dimensions();
+    let mut texture = 0;
+    unsafe {
+        GenTextures(1, &mut texture);
+        BindTexture(TEXTURE_2D, texture);
+        TexImage2D(
+            TEXTURE_2D,
+            0,
+            RGBA8 as i32,
+            width as i32,
+            height as i32,
+            0,
+            RGBA,
+            UNSIGNED_BYTE,
+            image.as_raw().as_ptr() as *const _,
+        );
+        if smooth {
+            GenerateMipmap(TEXTURE_2D);
+            TexParameteri(TEXTURE_2D, TEXTURE_MIN_FILTER, LINEAR_MIPMAP_LINEAR as i32);
+            TexParameteri(TEXTURE_2D, TEXTURE_MAG_FILTER, LINEAR as i32);
+        } else {
+            TexParameteri(TEXTURE_2D, TEXTURE_MIN_FILTER, NEAREST as i32);
+            TexParameteri(TEXTURE_2D, TEXTURE_MAG_FILTER, NEAREST as i32);
+        }
+        TexParameteri(TEXTURE_2D, TEXTURE_WRAP_S, CLAMP_TO_EDGE as i32);
+        TexParameteri(TEXTURE_2D, TEXTURE_WRAP_T, CLAMP_TO_EDGE as i32);
+        BindTexture(TEXTURE_2D, 0);
+    }
+    texture
+}
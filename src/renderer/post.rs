@@ -0,0 +1,213 @@
+use gl::types::GLuint;
+use nalgebra_glm as glm;
+
+use crate::gl_bindings::*;
+use crate::renderer::frame::FrameParams;
+use crate::renderer::framebuffer::RenderTarget;
+use crate::shader::ShaderProgram;
+
+const VERTEX_SHADER: &str = "shaders/post.vert";
+const BLOOM_SHADER: &str = "shaders/bloom.frag";
+const COMPOSITE_SHADER: &str = "shaders/post.frag";
+/// Every shader the pipeline loads.
+pub const SHADERS: [&str; 3] = [VERTEX_SHADER, BLOOM_SHADER, COMPOSITE_SHADER];
+
+/// Number of levels in the bloom mip chain, the first at half resolution.
+pub const BLOOM_LEVELS: usize = 5;
+
+/// Glow around the brightest parts of the frame.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Bloom {
+    pub enabled: bool,
+    /// Linear radiance above which a pixel starts to glow.
+    pub threshold: f32,
+    /// Weight of the blurred highlights added to the frame.
+    pub intensity: f32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self { enabled: true, threshold: 1.0, intensity: 0.15 }
+    }
+}
+
+impl Bloom {
+    /// `(min, max, step)` of `intensity` for the controls and scene files.
+    pub const INTENSITY_RANGE: (f32, f32, f32) = (0.0, 2.0, 0.05);
+}
+
+/// Sizes of the bloom levels for a `width`×`height` frame.
+pub fn bloom_level_sizes(width: u32, height: u32) -> Vec<(u32, u32)> {
+    let mut size = (width, height);
+    (0..BLOOM_LEVELS)
+        .map(|_| {
+            size = ((size.0 / 2).max(1), (size.1 / 2).max(1));
+            size
+        })
+        .collect()
+}
+
+/// HDR pipeline: the scene is drawn into a float target, bright areas are
+/// blurred down a mip chain and added back up, and a final pass applies
+/// exposure, tone mapping and gamma on the way to the output framebuffer.
+pub struct PostProcess {
+    /// Linear radiance of the frame, drawn by `blackhole.frag`. Like the
+    /// bloom targets it is only made by `resize`, at the size of the frames.
+    scene: Option<RenderTarget>,
+    levels: Vec<RenderTarget>,
+    /// Intermediate result of the horizontal blur of each level.
+    scratch: Vec<RenderTarget>,
+    bloom_program: ShaderProgram,
+    post_program: ShaderProgram,
+    vao: GLuint,
+}
+
+impl PostProcess {
+    /// Load the shaders, drawing the passes with the full-screen quad `vao`.
+    /// No targets exist until the first `resize`.
+    pub fn new(vao: GLuint) -> Result<Self, String> {
+        Ok(Self {
+            scene: None,
+            levels: Vec::new(),
+            scratch: Vec::new(),
+            bloom_program: ShaderProgram::new(VERTEX_SHADER, BLOOM_SHADER)?,
+            post_program: ShaderProgram::new(VERTEX_SHADER, COMPOSITE_SHADER)?,
+            vao,
+        })
+    }
+
+    /// Compile the bloom and composite shaders again, keeping the ones in use
+    /// unless both succeed.
+    pub fn reload(&mut self) -> Result<(), String> {
+        let bloom_program = ShaderProgram::new(VERTEX_SHADER, BLOOM_SHADER)?;
+        let post_program = ShaderProgram::new(VERTEX_SHADER, COMPOSITE_SHADER)?;
+        self.bloom_program = bloom_program;
+        self.post_program = post_program;
+        Ok(())
+    }
+
+    /// Make the targets for `width`×`height` frames, unless they already
+    /// have that size.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), String> {
+        if self.scene.as_ref().is_some_and(|scene| (scene.width, scene.height) == (width, height)) {
+            return Ok(());
+        }
+        // free the old targets first, so both sets never take up memory at once
+        self.scene = None;
+        self.levels.clear();
+        self.scratch.clear();
+        let sizes = bloom_level_sizes(width, height);
+        self.levels = sizes.iter().map(|&(w, h)| RenderTarget::hdr(w, h)).collect::<Result<_, _>>()?;
+        self.scratch = sizes.iter().map(|&(w, h)| RenderTarget::hdr(w, h)).collect::<Result<_, _>>()?;
+        self.scene = Some(RenderTarget::hdr(width, height)?);
+        Ok(())
+    }
+
+    /// The scene target of the last `resize`.
+    pub fn scene(&self) -> &RenderTarget {
+        self.scene.as_ref().expect("post-processing targets used before resize")
+    }
+
+    /// Bloom the contents of `scene` and draw the final image of `frame`
+    /// into framebuffer `output` (0 for the window) of the same size.
+    pub fn apply(&self, frame: &FrameParams, exposure_ev: f32, output: GLuint) {
+        if frame.bloom.enabled {
+            self.bloom(&frame.bloom);
+        }
+        self.composite(self.scene(), frame, exposure_ev, output, [0.0, 0.0, 1.0, 1.0]);
+    }
+
+    /// Add the bloom to `scene` and apply exposure, tone mapping and gamma on
+    /// the way to `output`. `bloom_rect` is the offset and size of `scene`
+    /// within the frame the bloom was made from, in texture coordinates.
+    pub fn composite(&self, scene: &RenderTarget, frame: &FrameParams, exposure_ev: f32, output: GLuint, bloom_rect: [f32; 4]) {
+        let program = &self.post_program;
+        unsafe {
+            BindFramebuffer(FRAMEBUFFER, output);
+            Viewport(0, 0, scene.width as i32, scene.height as i32);
+            BindVertexArray(self.vao);
+            program.bind();
+            ActiveTexture(TEXTURE0);
+            BindTexture(TEXTURE_2D, scene.texture);
+            program.set_sampler("u_scene", 0);
+            ActiveTexture(TEXTURE1);
+            BindTexture(TEXTURE_2D, self.levels[0].texture);
+            program.set_sampler("u_bloom", 1);
+            program.set_vec4("u_bloom_rect", &glm::Vec4::from(bloom_rect));
+            let intensity = if frame.bloom.enabled { frame.bloom.intensity } else { 0.0 };
+            program.set_f32("u_bloom_intensity", intensity);
+            program.set_i32("u_tonemap", frame.exposure.tonemap.index());
+            program.set_f32("u_exposure", 2f32.powf(exposure_ev));
+            DrawArrays(TRIANGLES, 0, 6);
+            ActiveTexture(TEXTURE0);
+            BindVertexArray(0);
+        }
+    }
+
+    /// Fill `levels[0]` with the sum of the blurred bright parts of every level.
+    pub fn bloom(&self, bloom: &Bloom) {
+        let program = &self.bloom_program;
+        unsafe {
+            BindVertexArray(self.vao);
+            ActiveTexture(TEXTURE0);
+        }
+        program.bind();
+        program.set_sampler("u_source", 0);
+        program.set_f32("u_threshold", bloom.threshold);
+
+        // bright pass at half resolution
+        self.bloom_pass(0, self.scene(), &self.levels[0]);
+
+        // each further level is a bilinear half of the previous one
+        for i in 1..BLOOM_LEVELS {
+            let (source, target) = (&self.levels[i - 1], &self.levels[i]);
+            unsafe {
+                BindFramebuffer(READ_FRAMEBUFFER, source.fbo);
+                BindFramebuffer(DRAW_FRAMEBUFFER, target.fbo);
+                BlitFramebuffer(
+                    0,
+                    0,
+                    source.width as i32,
+                    source.height as i32,
+                    0,
+                    0,
+                    target.width as i32,
+                    target.height as i32,
+                    COLOR_BUFFER_BIT,
+                    LINEAR,
+                );
+            }
+        }
+
+        // separable blur of every level
+        for (level, scratch) in self.levels.iter().zip(&self.scratch) {
+            program.set_vec2("u_direction", glm::vec2(1.0 / level.width as f32, 0.0));
+            self.bloom_pass(1, level, scratch);
+            program.set_vec2("u_direction", glm::vec2(0.0, 1.0 / level.height as f32));
+            self.bloom_pass(1, scratch, level);
+        }
+
+        // add each level onto the next larger one, smallest first
+        unsafe {
+            Enable(BLEND);
+            BlendFunc(ONE, ONE);
+        }
+        for i in (1..BLOOM_LEVELS).rev() {
+            self.bloom_pass(2, &self.levels[i], &self.levels[i - 1]);
+        }
+        unsafe {
+            Disable(BLEND);
+            BindVertexArray(0);
+        }
+    }
+
+    /// One pass of `bloom.frag` from `source` into `target`.
+    fn bloom_pass(&self, pass: i32, source: &RenderTarget, target: &RenderTarget) {
+        target.bind();
+        self.bloom_program.set_i32("u_pass", pass);
+        unsafe {
+            BindTexture(TEXTURE_2D, source.texture);
+            DrawArrays(TRIANGLES, 0, 6);
+        }
+    }
+}
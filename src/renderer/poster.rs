@@ -0,0 +1,149 @@
+use std::io::Write;
+use std::time::Instant;
+
+/// Largest tile drawn at once unless `--tile-size` says otherwise; small
+/// enough to stay clear of GPU driver timeouts at high quality.
+pub const DEFAULT_TILE_SIZE: u32 = 2048;
+/// Longer side of the preview a tiled render takes its exposure and bloom
+/// from, so they are the same in every tile.
+pub const PREVIEW_SIZE: u32 = 2048;
+
+/// One piece of a tiled render, in pixels from the top left of the image.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    /// Offset of the tile's bottom-left pixel in a frame `height` pixels
+    /// high, as `u_tile_offset` expects it.
+    pub fn gl_offset(&self, height: u32) -> (u32, u32) {
+        (self.x, height - self.y - self.height)
+    }
+
+    /// Offset and size of the tile in texture coordinates of a
+    /// `width`×`height` frame, as `u_bloom_rect` expects them.
+    pub fn texture_rect(&self, width: u32, height: u32) -> [f32; 4] {
+        let (x, y) = self.gl_offset(height);
+        [
+            x as f32 / width as f32,
+            y as f32 / height as f32,
+            self.width as f32 / width as f32,
+            self.height as f32 / height as f32,
+        ]
+    }
+}
+
+/// Split a `width`×`height` image into tiles of at most `size`×`size`, row by
+/// row from the top.
+pub fn tile_grid(width: u32, height: u32, size: u32) -> Vec<Tile> {
+    let size = size.max(1);
+    (0..height)
+        .step_by(size as usize)
+        .flat_map(|y| {
+            (0..width).step_by(size as usize).map(move |x| Tile {
+                x,
+                y,
+                width: size.min(width - x),
+                height: size.min(height - y),
+            })
+        })
+        .collect()
+}
+
+/// Size of the preview of a `width`×`height` image: the same aspect, at most
+/// `PREVIEW_SIZE` on the longer side.
+pub fn preview_size(width: u32, height: u32) -> (u32, u32) {
+    let scale = (PREVIEW_SIZE as f64 / width.max(height) as f64).min(1.0);
+    let scaled = |n: u32| ((n as f64 * scale).round() as u32).max(1);
+    (scaled(width), scaled(height))
+}
+
+/// Copy the top-to-bottom RGBA rows of `tile` into `image`, which is `width`
+/// pixels wide.
+pub fn stitch<T: Copy>(image: &mut [T], width: u32, tile: &Tile, pixels: &[T]) {
+    let row = tile.width as usize * 4;
+    for (i, src) in pixels.chunks_exact(row).enumerate() {
+        let start = ((tile.y as usize + i) * width as usize + tile.x as usize) * 4;
+        image[start..start + row].copy_from_slice(src);
+    }
+}
+
+/// Rewrite the progress line of a long render after `done` of `total` pieces
+/// of kind `what`, such as tiles.
+pub fn report_progress(what: &str, done: usize, total: usize, start: Instant) {
+    let elapsed = start.elapsed();
+    let remaining = elapsed.mul_f64((total - done) as f64 / done.max(1) as f64);
+    print!(
+        "\rRendered {} {}/{} ({:.0}%), {:.0?} elapsed, about {:.0?} left   ",
+        what,
+        done,
+        total,
+        100.0 * done as f64 / total as f64,
+        elapsed,
+        remaining
+    );
+    if done == total {
+        println!();
+    }
+    let _ = std::io::stdout().flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_covers_the_image_with_partial_last_row_and_column() {
+        let tiles = tile_grid(5, 3, 2);
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(tiles[0], Tile { x: 0, y: 0, width: 2, height: 2 });
+        assert_eq!(tiles[2], Tile { x: 4, y: 0, width: 1, height: 2 });
+        assert_eq!(tiles[5], Tile { x: 4, y: 2, width: 1, height: 1 });
+        let area: u32 = tiles.iter().map(|t| t.width * t.height).sum();
+        assert_eq!(area, 15);
+    }
+
+    #[test]
+    fn small_images_are_a_single_tile() {
+        assert_eq!(tile_grid(1, 1, 2048), vec![Tile { x: 0, y: 0, width: 1, height: 1 }]);
+        assert_eq!(tile_grid(300, 200, 300), vec![Tile { x: 0, y: 0, width: 300, height: 200 }]);
+        let tile = Tile { x: 0, y: 0, width: 1, height: 1 };
+        assert_eq!(tile.gl_offset(1), (0, 0));
+        assert_eq!(tile.texture_rect(1, 1), [0.0, 0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn gl_offsets_count_from_the_bottom() {
+        let tiles = tile_grid(5, 3, 2);
+        // the top row of tiles sits above the partial bottom row
+        assert_eq!(tiles[0].gl_offset(3), (0, 1));
+        assert_eq!(tiles[5].gl_offset(3), (4, 0));
+        assert_eq!(tiles[1].texture_rect(5, 3), [2.0 / 5.0, 1.0 / 3.0, 2.0 / 5.0, 2.0 / 3.0]);
+    }
+
+    #[test]
+    fn stitched_tiles_rebuild_the_image() {
+        let (width, height) = (5u32, 3u32);
+        let value = |x: u32, y: u32| (y * width + x) as u8;
+        let mut image = vec![0u8; (width * height * 4) as usize];
+        for tile in tile_grid(width, height, 2) {
+            // draw the tile the way GL does, bottom row first, then read it
+            // back top to bottom as `read_pixels` does
+            let (gl_x, gl_y) = tile.gl_offset(height);
+            let mut rows: Vec<Vec<u8>> = (0..tile.height)
+                .map(|r| {
+                    let y = height - 1 - (gl_y + r);
+                    (0..tile.width).flat_map(|c| [value(gl_x + c, y); 4]).collect()
+                })
+                .collect();
+            rows.reverse();
+            stitch(&mut image, width, &tile, &rows.concat());
+        }
+        let expected: Vec<u8> = (0..height).flat_map(|y| (0..width).flat_map(move |x| [value(x, y); 4])).collect();
+        assert_eq!(image, expected);
+    }
+}
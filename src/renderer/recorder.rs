@@ -0,0 +1,269 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::Instant;
+
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+
+use crate::renderer::framebuffer::read_rgba;
+
+/// Frame rate of recordings unless `--record-fps` says otherwise.
+pub const DEFAULT_RECORD_FPS: f64 = 30.0;
+
+/// Where a YUV4MPEG2 stream goes.
+#[derive(Debug, Clone)]
+pub enum RecordTarget {
+    File(PathBuf),
+    /// Shell command that reads the stream on its stdin, such as an encoder.
+    Command(String),
+}
+
+impl RecordTarget {
+    pub fn describe(&self) -> String {
+        match self {
+            RecordTarget::File(path) => path.display().to_string(),
+            RecordTarget::Command(command) => format!("`{}`", command),
+        }
+    }
+}
+
+/// Writes RGBA frames as an uncompressed YUV4MPEG2 (.y4m) stream in 4:2:0
+/// with BT.601 studio-range colours, which ffmpeg and most players read as is.
+pub struct Y4mWriter {
+    out: Box<dyn Write>,
+    /// Encoder process when writing to a command.
+    child: Option<Child>,
+    pub width: u32,
+    pub height: u32,
+    /// Frames written so far.
+    pub frames: u64,
+}
+
+impl Y4mWriter {
+    /// Open `target` and write the stream header for `width`×`height` frames
+    /// at `fps` frames per second.
+    pub fn create(target: &RecordTarget, width: u32, height: u32, fps: f64) -> Result<Self, String> {
+        let (out, child): (Box<dyn Write>, _) = match target {
+            RecordTarget::File(path) => {
+                if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+                }
+                let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+                (Box::new(BufWriter::new(file)), None)
+            }
+            RecordTarget::Command(command) => {
+                let mut child = shell(command)
+                    .stdin(Stdio::piped())
+                    .spawn()
+                    .map_err(|e| format!("Failed to start `{}`: {}", command, e))?;
+                let stdin = child.stdin.take().ok_or("Failed to open the encoder's stdin")?;
+                (Box::new(BufWriter::new(stdin)), Some(child))
+            }
+        };
+
+        let mut writer = Self { out, child, width, height, frames: 0 };
+        let (numerator, denominator) = frame_rate(fps);
+        let header = format!("YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C420jpeg\n", width, height, numerator, denominator);
+        writer.write(header.as_bytes())?;
+        Ok(writer)
+    }
+
+    /// Append a frame given as top-to-bottom RGBA rows of the stream's size.
+    pub fn write_frame(&mut self, pixels: &[u8]) -> Result<(), String> {
+        let (y, u, v) = rgba_to_yuv420(self.width, self.height, pixels);
+        self.write(b"FRAME\n")?;
+        self.write(&y)?;
+        self.write(&u)?;
+        self.write(&v)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Flush the stream and, for a command, close its input and wait for it
+    /// to exit, even if the stream broke, since its exit status tells why.
+    /// Returns the number of frames written.
+    pub fn finish(mut self) -> Result<u64, String> {
+        let flushed = self.out.flush().map_err(write_error);
+        drop(self.out);
+        if let Some(mut child) = self.child {
+            let status = child.wait().map_err(|e| format!("Failed to wait for the encoder: {}", e))?;
+            if !status.success() {
+                return Err(format!("Encoder exited with {}", status));
+            }
+        }
+        flushed.map(|()| self.frames)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.out.write_all(bytes).map_err(write_error)
+    }
+}
+
+fn write_error(e: std::io::Error) -> String {
+    match e.kind() {
+        std::io::ErrorKind::BrokenPipe => "Encoder stopped reading frames".to_string(),
+        _ => format!("Failed to write frame: {}", e),
+    }
+}
+
+/// Recording of the window at a fixed frame rate, for the record key.
+pub struct Recorder {
+    pub writer: Y4mWriter,
+    pub target: RecordTarget,
+    pub fps: f64,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn start(target: RecordTarget, width: u32, height: u32, fps: f64) -> Result<Self, String> {
+        let writer = Y4mWriter::create(&target, width, height, fps)?;
+        Ok(Self { writer, target, fps, start: Instant::now() })
+    }
+
+    /// Add the window's `width`×`height` framebuffer to the recording, once
+    /// for every frame period that began since the last call. The video
+    /// thus plays back in real time however fast the app draws. Frames of
+    /// another size than the stream are scaled to it.
+    pub fn capture(&mut self, width: u32, height: u32) -> Result<(), String> {
+        let due = frames_due(self.start.elapsed().as_secs_f64(), self.fps);
+        if due <= self.writer.frames {
+            return Ok(());
+        }
+
+        let mut pixels = read_rgba(width, height);
+        if (width, height) != (self.writer.width, self.writer.height) {
+            let image = RgbaImage::from_raw(width, height, pixels).ok_or("Pixel buffer does not match window size")?;
+            pixels = imageops::resize(&image, self.writer.width, self.writer.height, FilterType::Triangle).into_raw();
+        }
+        while self.writer.frames < due {
+            self.writer.write_frame(&pixels)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<u64, String> {
+        self.writer.finish()
+    }
+}
+
+/// `command` run by the platform's shell.
+fn shell(command: &str) -> Command {
+    let mut shell = if cfg!(windows) { Command::new("cmd") } else { Command::new("sh") };
+    shell.arg(if cfg!(windows) { "/C" } else { "-c" }).arg(command);
+    shell
+}
+
+/// Frames a real-time recording at `fps` should hold `elapsed` seconds
+/// after it started: one for every frame period that has begun.
+fn frames_due(elapsed: f64, fps: f64) -> u64 {
+    (elapsed * fps) as u64 + 1
+}
+
+/// `fps` as the ratio the Y4M header expects.
+fn frame_rate(fps: f64) -> (u64, u64) {
+    if fps.fract() == 0.0 {
+        (fps as u64, 1)
+    } else {
+        ((fps * 1000.0).round() as u64, 1000)
+    }
+}
+
+/// Planes of a `width`×`height` RGBA image in BT.601 studio range, with
+/// chroma averaged over 2×2 blocks.
+fn rgba_to_yuv420(width: u32, height: u32, pixels: &[u8]) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+    let (width, height) = (width as usize, height as usize);
+    let rgb = |x: usize, y: usize| {
+        let i = (y * width + x) * 4;
+        [pixels[i] as f32, pixels[i + 1] as f32, pixels[i + 2] as f32]
+    };
+
+    let mut luma = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let [r, g, b] = rgb(x, y);
+            luma.push((16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0).round() as u8);
+        }
+    }
+
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    let mut cb = Vec::with_capacity(chroma_width * chroma_height);
+    let mut cr = Vec::with_capacity(chroma_width * chroma_height);
+    for cy in 0..chroma_height {
+        for cx in 0..chroma_width {
+            let mut sum = [0.0f32; 3];
+            let mut count = 0.0;
+            for y in (2 * cy)..(2 * cy + 2).min(height) {
+                for x in (2 * cx)..(2 * cx + 2).min(width) {
+                    let [r, g, b] = rgb(x, y);
+                    sum = [sum[0] + r, sum[1] + g, sum[2] + b];
+                    count += 1.0;
+                }
+            }
+            let [r, g, b] = sum.map(|c| c / count);
+            cb.push((128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0).round() as u8);
+            cr.push((128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0).round() as u8);
+        }
+    }
+    (luma, cb, cr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32, color: impl Fn(u32, u32) -> [u8; 3]) -> Vec<u8> {
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                let [r, g, b] = color(x, y);
+                [r, g, b, 255]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn converts_to_bt601_studio_range() {
+        for (color, yuv) in [([0, 0, 0], (16, 128, 128)), ([255, 255, 255], (235, 128, 128)), ([255, 0, 0], (81, 90, 240))] {
+            let (y, u, v) = rgba_to_yuv420(2, 2, &image(2, 2, |_, _| color));
+            assert_eq!((y, u, v), (vec![yuv.0; 4], vec![yuv.1], vec![yuv.2]), "{:?}", color);
+        }
+    }
+
+    #[test]
+    fn averages_chroma_over_partial_blocks_at_odd_sizes() {
+        // red in the last column only, which has a block of its own
+        let (y, u, v) = rgba_to_yuv420(3, 3, &image(3, 3, |x, _| if x == 2 { [255, 0, 0] } else { [0, 0, 0] }));
+        assert_eq!(y, [16, 16, 81].repeat(3));
+        assert_eq!((u, v), (vec![128, 90, 128, 90], vec![128, 240, 128, 240]));
+
+        // half of a full block red
+        let (_, u, v) = rgba_to_yuv420(2, 2, &image(2, 2, |x, _| if x == 0 { [255, 0, 0] } else { [0, 0, 0] }));
+        assert_eq!((u, v), (vec![109], vec![184]));
+    }
+
+    #[test]
+    fn writes_the_stream_header_and_frames() {
+        let path = std::env::temp_dir().join(format!("blackhole-recorder-{}.y4m", std::process::id()));
+        let mut writer = Y4mWriter::create(&RecordTarget::File(path.clone()), 3, 3, 29.97).unwrap();
+        writer.write_frame(&image(3, 3, |_, _| [0, 0, 0])).unwrap();
+        assert_eq!(writer.finish().unwrap(), 1);
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let header = b"YUV4MPEG2 W3 H3 F29970:1000 Ip A1:1 C420jpeg\nFRAME\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(bytes.len(), header.len() + 9 + 2 * 4);
+        assert_eq!(frame_rate(30.0), (30, 1));
+    }
+
+    #[test]
+    fn counts_frames_due_in_real_time() {
+        assert_eq!(frames_due(0.0, 30.0), 1);
+        assert_eq!(frames_due(0.033, 30.0), 1);
+        assert_eq!(frames_due(0.034, 30.0), 2);
+        // a 0.5 s stall at 30 fps is made up with duplicates
+        assert_eq!(frames_due(1.5, 30.0), 46);
+    }
+}
@@ -1,6 +1,37 @@
 use std::path::Path;
 use crate::gl_bindings::*;
 use gl::types::{GLuint, GLenum};
+use image::RgbImage;
+
+/// Cubemap faces in `TEXTURE_CUBE_MAP_POSITIVE_X + i` order.
+const FACES: [&str; 6] = [
+    "right.png",
+    "left.png",
+    "top.png",
+    "bottom.png",
+    "front.png",
+    "back.png",
+];
+
+/// Load the six faces of a skybox folder oriented the way they are uploaded,
+/// so row 0 of each image is texture coordinate `t = 0`.
+pub fn load_faces<P: AsRef<Path>>(folder: P) -> Result<Vec<RgbImage>, String> {
+    FACES
+        .iter()
+        .map(|face| {
+            let path = folder.as_ref().join(face);
+            let mut dyn_img = image::open(&path)
+                .map_err(|_| format!("Failed to load cubemap face {:?}", path))?
+                .flipv();
+
+            if *face == "top.png" || *face == "bottom.png" {
+                dyn_img = dyn_img.rotate180();
+            }
+
+            Ok(dyn_img.to_rgb8())
+        })
+        .collect()
+}
 
 pub struct Skybox {
     pub id: GLuint,
@@ -8,14 +39,7 @@ pub struct Skybox {
 
 impl Skybox {
     pub fn load_from_folder<P: AsRef<Path>>(folder: P) -> Result<Self, String> {
-        let faces = [
-            "right.png",
-            "left.png",
-            "top.png",
-            "bottom.png",
-            "front.png",
-            "back.png",
-        ];
+        let faces = load_faces(folder)?;
 
         let mut texture_id: GLuint = 0;
         unsafe {
@@ -23,20 +47,8 @@ impl Skybox {
             BindTexture(TEXTURE_CUBE_MAP, texture_id);
         }
 
-        for (i, face) in faces.iter().enumerate() {
-            let path = folder.as_ref().join(face);
-            let mut dyn_img = image::open(&path)
-                .map_err(|_| format!("Failed to load cubemap face {:?}", path))?
-                .flipv();
-
-                if *face == "top.png" || *face == "bottom.png" {
-                dyn_img = dyn_img.rotate180();
-            }
-
-            let img = dyn_img.to_rgb8();
-
+        for (i, img) in faces.iter().enumerate() {
             let (width, height) = img.dimensions();
-            let data = img.into_raw();
 
             unsafe {
                 TexImage2D(
@@ -48,7 +60,7 @@ impl Skybox {
                     0,
                     RGB,
                     UNSIGNED_BYTE,
-                    data.as_ptr() as *const _,
+                    img.as_ptr() as *const _,
                 );
             }
         }
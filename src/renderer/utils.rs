@@ -1,14 +1,8 @@
-use std::ffi::CString;
+use std::path::Path;
 use crate::gl_bindings::*;
+use crate::metadata::RenderMetadata;
 use gl::types::{GLuint};
 
-pub fn get_uniform(program_id: u32, name: &str) -> i32 {
-    unsafe {
-        let cname = CString::new(name).unwrap();
-        GetUniformLocation(program_id, cname.as_ptr())
-    }
-}
-
 pub fn load_texture(path: &str) -> Result<u32, String> {
     let img = image::open(path).map_err(|e| e.to_string())?.flipv().to_rgb8();
     let (width, height) = img.dimensions();
@@ -34,3 +28,36 @@ pub fn load_texture(path: &str) -> Result<u32, String> {
 
     Ok(texture_id)
 }
+
+/// Save top-to-bottom RGBA rows as an image, creating the parent directory if
+/// needed. The format follows the file extension; PNG files get `metadata`
+/// embedded.
+pub fn save_rgba(path: &Path, width: u32, height: u32, pixels: Vec<u8>, metadata: &RenderMetadata) -> Result<(), String> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    if path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| ext.eq_ignore_ascii_case("png")) {
+        return metadata.save_png(path, width, height, &pixels);
+    }
+    let img = image::RgbaImage::from_raw(width, height, pixels).ok_or("Pixel buffer does not match image size")?;
+    img.save(path).map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+}
+
+/// Whether `path` is an `.exr` or `.hdr` file, which store linear radiance
+/// instead of the tone mapped image.
+pub fn is_hdr_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("exr") || ext.eq_ignore_ascii_case("hdr"))
+}
+
+/// `save_rgba` for top-to-bottom RGBA float rows, written as OpenEXR or
+/// Radiance HDR depending on the extension. Alpha is dropped.
+pub fn save_rgba_f32(path: &Path, width: u32, height: u32, pixels: Vec<f32>) -> Result<(), String> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let rgb = pixels.chunks_exact(4).flat_map(|p| [p[0], p[1], p[2]]).collect();
+    let img = image::Rgb32FImage::from_raw(width, height, rgb).ok_or("Pixel buffer does not match image size")?;
+    img.save(path).map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+}
@@ -8,18 +8,32 @@ pub struct WindowContext {
 }
 
 impl WindowContext {
-	pub fn new(width: u32, height: u32, title: &str) -> Self {
-		let mut glfw = glfw::init(glfw::fail_on_errors).unwrap();
+	pub fn new(width: u32, height: u32, title: &str, vsync: bool) -> Self {
+		Self::create(width, height, title, true, vsync).unwrap_or_else(|e| panic!("{}", e))
+	}
+
+	/// An invisible window that only exists to own a GL context, for rendering
+	/// offscreen into a `RenderTarget`. Fails rather than panics without a
+	/// display, so headless renders can say what to do instead.
+	pub fn hidden(title: &str) -> Result<Self, String> {
+		Self::create(64, 64, title, false, false)
+	}
+
+	fn create(width: u32, height: u32, title: &str, visible: bool, vsync: bool) -> Result<Self, String> {
+		// errors only panic once the window exists; until then they are returned
+		let mut glfw = glfw::init(glfw::log_errors).map_err(|e| format!("Failed to initialise GLFW: {}", e))?;
 		glfw.window_hint(glfw::WindowHint::ContextVersion(3, 3));
 		glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
 		glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
+		glfw.window_hint(glfw::WindowHint::Visible(visible));
 
 		let (mut window, events) = glfw
 			.create_window(width, height, title, glfw::WindowMode::Windowed)
-			.expect("Failed to create GLFW window");
+			.ok_or_else(|| "Failed to create GLFW window".to_string())?;
+		glfw.set_error_callback(glfw::fail_on_errors);
 
 		window.make_current();
-		glfw.set_swap_interval(glfw::SwapInterval::Sync(1));
+		glfw.set_swap_interval(if vsync { glfw::SwapInterval::Sync(1) } else { glfw::SwapInterval::None });
 
 		load_with(|symbol| window.get_proc_address(symbol) as *const _);
 
@@ -31,7 +45,7 @@ impl WindowContext {
 			eprintln!("OpenGL vendor: {} | renderer: {} | version: {}", vendor, renderer, version);
 		}
 
-		Self { glfw, window, events }
+		Ok(Self { glfw, window, events })
 	}
 
 	pub fn poll(&mut self) {
@@ -0,0 +1,649 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use nalgebra_glm as glm;
+
+use crate::camera::{Camera, CameraMode, CameraType};
+use crate::cli::Options;
+use crate::metadata::RenderMetadata;
+use crate::physics::black_hole::BlackHole;
+use crate::renderer::disk::{DiskParam, DiskParams, EmissionMode};
+use crate::renderer::exposure::Exposure;
+use crate::renderer::frame::Quality;
+use crate::renderer::post::Bloom;
+
+/// Everything that defines a view, so a figure can be saved and reproduced
+/// exactly. Stored as a small TOML file:
+///
+/// ```toml
+/// [camera]
+/// mode = "free_orbit"
+/// type = "locked"
+/// radius = 15.0
+/// azimuth = 45.0
+/// elevation = 81.0
+/// roll = 0.0
+/// position = [1.65, 2.35, 10.48]
+/// fov = 60.0
+///
+/// [black_hole]
+/// mass = 4297000.0
+/// spin = 0.0
+///
+/// [disk]
+/// enabled = true
+/// redshift = true
+/// emission = "color_map"
+/// isco = true
+/// inner = 2.6
+/// outer = 12.0
+/// height = 1.0
+/// density_v = 2.0
+/// density_h = 1.0
+/// noise_scale = 1.0
+/// speed = 0.5
+/// density_scale = 160.0
+/// absorption = 0.02
+/// log_accretion_rate = -7.0
+///
+/// [render]
+/// lensing = true
+/// quality = "medium"
+/// time = 0.0
+/// tonemap = "reinhard"
+/// exposure = 0.0
+/// auto_exposure = false
+/// bloom = true
+/// bloom_threshold = 1.0
+/// bloom_intensity = 0.15
+/// ```
+///
+/// Angles are in degrees and the mass is in solar masses. Keys that are left
+/// out keep their default.
+#[derive(Clone)]
+pub struct Scene {
+    pub camera_mode: CameraMode,
+    pub camera_type: CameraType,
+    /// Orbit radius of the locked camera in r_s.
+    pub radius: f32,
+    pub azimuth: f32,
+    /// Angle from the spin axis; 90 is the disk plane.
+    pub elevation: f32,
+    pub roll: f32,
+    /// Position of the free camera.
+    pub position: glm::Vec3,
+    pub fov: f32,
+    pub black_hole: BlackHole,
+    pub render_disk: bool,
+    pub disk: DiskParams,
+    /// Doppler beaming and gravitational redshift of the disk emission.
+    pub redshift: bool,
+    pub emission_mode: EmissionMode,
+    pub gravitational_lensing: bool,
+    pub quality: Quality,
+    /// Shader time, which drives the disk animation and the auto orbit.
+    pub time: f32,
+    pub exposure: Exposure,
+    pub bloom: Bloom,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        let mut scene = Self {
+            camera_mode: CameraMode::FreeOrbit,
+            camera_type: CameraType::LockedCam,
+            radius: 0.0,
+            azimuth: 0.0,
+            elevation: 0.0,
+            roll: 0.0,
+            position: glm::Vec3::zeros(),
+            fov: 60.0,
+            black_hole: BlackHole::default(),
+            render_disk: true,
+            disk: DiskParams::default(),
+            redshift: true,
+            emission_mode: EmissionMode::ColorMap,
+            gravitational_lensing: true,
+            quality: Quality::Medium,
+            time: 0.0,
+            exposure: Exposure::default(),
+            bloom: Bloom::default(),
+        };
+        scene.set_camera(&Camera::new());
+        scene
+    }
+}
+
+impl Scene {
+    /// Copy the state of a camera into the scene.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.camera_mode = camera.mode;
+        self.camera_type = camera.camera_type;
+        self.radius = camera.target_radius;
+        self.azimuth = camera.azimuth.to_degrees();
+        self.elevation = camera.elevation.to_degrees();
+        self.roll = camera.roll.to_degrees();
+        self.position = camera.free_position;
+    }
+
+    /// The camera this scene describes.
+    pub fn camera(&self) -> Camera {
+        let mut camera = Camera::new();
+        camera.mode = self.camera_mode;
+        camera.camera_type = self.camera_type;
+        camera.radius = self.radius.clamp(camera.min_radius, camera.max_radius);
+        camera.target_radius = camera.radius;
+        camera.azimuth = self.azimuth.to_radians();
+        camera.elevation = self.elevation.to_radians();
+        camera.roll = self.roll.to_radians();
+        camera.free_position = self.position;
+        camera.target_distance = glm::length(&self.position);
+        camera
+    }
+
+    /// The starting scene: the `--scene` file or the scene embedded in the
+    /// `--from-image` render if one was given, with any view options from the
+    /// command line applied on top.
+    pub fn from_options(options: &Options) -> Result<Self, String> {
+        let mut scene = match (&options.scene, &options.from_image) {
+            (Some(path), _) => Self::load(path)?,
+            (None, Some(path)) => RenderMetadata::read(path)?.scene,
+            (None, None) => Self::default(),
+        };
+        scene.apply_options(options);
+        Ok(scene)
+    }
+
+    /// Override the scene with the view options given on the command line.
+    pub fn apply_options(&mut self, options: &Options) {
+        if let Some(radius) = options.radius {
+            self.radius = radius;
+        }
+        if let Some(azimuth) = options.azimuth {
+            self.azimuth = azimuth;
+        }
+        if let Some(elevation) = options.elevation {
+            self.elevation = elevation;
+        }
+        if let Some(fov) = options.fov {
+            self.fov = fov;
+        }
+        if let Some(spin) = options.spin {
+            self.black_hole.spin = spin;
+        }
+        if let Some(mass) = options.mass {
+            self.black_hole.mass = mass;
+        }
+        if let Some(render_disk) = options.render_disk {
+            self.render_disk = render_disk;
+        }
+        if let Some(redshift) = options.redshift {
+            self.redshift = redshift;
+        }
+        if let Some(emission_mode) = options.emission_mode {
+            self.emission_mode = emission_mode;
+        }
+        if let Some(gravitational_lensing) = options.gravitational_lensing {
+            self.gravitational_lensing = gravitational_lensing;
+        }
+        if let Some(quality) = options.quality {
+            self.quality = quality;
+        }
+        if let Some(time) = options.time {
+            self.time = time;
+        }
+        if let Some(tonemap) = options.tonemap {
+            self.exposure.tonemap = tonemap;
+        }
+        if let Some(ev) = options.exposure {
+            self.exposure.ev = ev;
+        }
+        if let Some(auto) = options.auto_exposure {
+            self.exposure.auto = auto;
+        }
+        if let Some(bloom) = options.bloom {
+            self.bloom.enabled = bloom;
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        std::fs::write(path, self.to_toml()).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut doc = Document::parse(text)?;
+        let scene = Self::read(&mut doc, Self::default())?;
+        doc.finish()?;
+        Ok(scene)
+    }
+
+    /// `scene` with the values of the scene keys in `doc` applied on top,
+    /// leaving any other keys for the caller.
+    pub fn read(doc: &mut Document, mut scene: Scene) -> Result<Self, String> {
+        if let Some(mode) = doc.text("camera.mode")? {
+            scene.camera_mode = match mode.as_str() {
+                "free_orbit" => CameraMode::FreeOrbit,
+                "auto_orbit" => CameraMode::AutoOrbit,
+                "front" => CameraMode::FrontView,
+                "top" => CameraMode::TopView,
+                _ => return Err(format!("unknown camera mode \"{}\"", mode)),
+            };
+        }
+        if let Some(camera_type) = doc.text("camera.type")? {
+            scene.camera_type = match camera_type.as_str() {
+                "locked" => CameraType::LockedCam,
+                "free" => CameraType::FreeCam,
+                _ => return Err(format!("unknown camera type \"{}\"", camera_type)),
+            };
+        }
+        doc.number("camera.radius", &mut scene.radius)?;
+        doc.number("camera.azimuth", &mut scene.azimuth)?;
+        doc.number("camera.elevation", &mut scene.elevation)?;
+        doc.number("camera.roll", &mut scene.roll)?;
+        doc.vec3("camera.position", &mut scene.position)?;
+        doc.number("camera.fov", &mut scene.fov)?;
+        let numbers = [
+            ("camera.radius", scene.radius),
+            ("camera.azimuth", scene.azimuth),
+            ("camera.elevation", scene.elevation),
+            ("camera.roll", scene.roll),
+        ];
+        if let Some((key, _)) = numbers.iter().find(|(_, value)| !value.is_finite()) {
+            return Err(format!("{} must be a finite number", key));
+        }
+        if !scene.position.iter().all(|c| c.is_finite()) {
+            return Err("camera.position must be finite".to_string());
+        }
+        if scene.fov.is_nan() || scene.fov <= 0.0 || scene.fov >= 180.0 {
+            return Err("camera.fov must lie between 0 and 180 degrees".to_string());
+        }
+        doc.number("black_hole.mass", &mut scene.black_hole.mass)?;
+        doc.number("black_hole.spin", &mut scene.black_hole.spin)?;
+        doc.boolean("disk.enabled", &mut scene.render_disk)?;
+        doc.boolean("disk.redshift", &mut scene.redshift)?;
+        if let Some(emission) = doc.text("disk.emission")? {
+            scene.emission_mode = emission.parse().map_err(|_| format!("unknown emission mode \"{}\"", emission))?;
+        }
+        doc.boolean("disk.isco", &mut scene.disk.isco)?;
+        for param in DiskParam::ALL {
+            let key = format!("disk.{}", param.name());
+            doc.number(&key, scene.disk.get_mut(param))?;
+            let (min, max, _) = param.range();
+            if !(min..=max).contains(&scene.disk.get(param)) {
+                return Err(format!("{} must lie between {} and {}", key, min, max));
+            }
+        }
+        if scene.disk.outer <= scene.disk.inner {
+            return Err("disk.outer must be larger than disk.inner".to_string());
+        }
+        doc.boolean("render.lensing", &mut scene.gravitational_lensing)?;
+        if let Some(quality) = doc.text("render.quality")? {
+            scene.quality = quality.parse().map_err(|_| format!("unknown quality \"{}\"", quality))?;
+        }
+        doc.number("render.time", &mut scene.time)?;
+        if let Some(tonemap) = doc.text("render.tonemap")? {
+            scene.exposure.tonemap = tonemap.parse().map_err(|_| format!("unknown tone mapping operator \"{}\"", tonemap))?;
+        }
+        doc.number("render.exposure", &mut scene.exposure.ev)?;
+        doc.boolean("render.auto_exposure", &mut scene.exposure.auto)?;
+        doc.boolean("render.bloom", &mut scene.bloom.enabled)?;
+        doc.number("render.bloom_threshold", &mut scene.bloom.threshold)?;
+        doc.number("render.bloom_intensity", &mut scene.bloom.intensity)?;
+        if scene.bloom.threshold.is_nan() || scene.bloom.threshold < 0.0 {
+            return Err("render.bloom_threshold must not be negative".to_string());
+        }
+        let (min, max, _) = Bloom::INTENSITY_RANGE;
+        if !(min..=max).contains(&scene.bloom.intensity) {
+            return Err(format!("render.bloom_intensity must lie between {} and {}", min, max));
+        }
+        if scene.black_hole.spin.is_nan() || scene.black_hole.spin.abs() >= 1.0 {
+            return Err("black_hole.spin must lie strictly between -1 and 1".to_string());
+        }
+        if !(scene.black_hole.mass.is_finite() && scene.black_hole.mass > 0.0) {
+            return Err("black_hole.mass must be positive".to_string());
+        }
+        Ok(scene)
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mode = match self.camera_mode {
+            CameraMode::FreeOrbit => "free_orbit",
+            CameraMode::AutoOrbit => "auto_orbit",
+            CameraMode::FrontView => "front",
+            CameraMode::TopView => "top",
+        };
+        let camera_type = match self.camera_type {
+            CameraType::LockedCam => "locked",
+            CameraType::FreeCam => "free",
+        };
+        let disk: String = DiskParam::ALL
+            .iter()
+            .map(|&param| format!("{} = {:?}\n", param.name(), self.disk.get(param)))
+            .collect();
+
+        format!(
+            "[camera]\n\
+             mode = \"{}\"\n\
+             type = \"{}\"\n\
+             radius = {:?}\n\
+             azimuth = {:?}\n\
+             elevation = {:?}\n\
+             roll = {:?}\n\
+             position = [{:?}, {:?}, {:?}]\n\
+             fov = {:?}\n\
+             \n\
+             [black_hole]\n\
+             mass = {:?}\n\
+             spin = {:?}\n\
+             \n\
+             [disk]\n\
+             enabled = {}\n\
+             redshift = {}\n\
+             emission = \"{}\"\n\
+             isco = {}\n\
+             {}\
+             \n\
+             [render]\n\
+             lensing = {}\n\
+             quality = \"{}\"\n\
+             time = {:?}\n\
+             tonemap = \"{}\"\n\
+             exposure = {:?}\n\
+             auto_exposure = {}\n\
+             bloom = {}\n\
+             bloom_threshold = {:?}\n\
+             bloom_intensity = {:?}\n",
+            mode,
+            camera_type,
+            self.radius,
+            self.azimuth,
+            self.elevation,
+            self.roll,
+            self.position.x,
+            self.position.y,
+            self.position.z,
+            self.fov,
+            self.black_hole.mass,
+            self.black_hole.spin,
+            self.render_disk,
+            self.redshift,
+            self.emission_mode.name(),
+            self.disk.isco,
+            disk,
+            self.gravitational_lensing,
+            self.quality.name(),
+            self.time,
+            self.exposure.tonemap.name(),
+            self.exposure.ev,
+            self.exposure.auto,
+            self.bloom.enabled,
+            self.bloom.threshold,
+            self.bloom.intensity,
+        )
+    }
+}
+
+/// Value of a key in the TOML subset scene files use.
+#[derive(Debug)]
+enum Value {
+    Number(f64),
+    Bool(bool),
+    Text(String),
+    Array(Vec<f64>),
+}
+
+/// Numeric field types `Document::number` can fill.
+pub trait Number {
+    fn from_f64(v: f64) -> Self;
+}
+
+impl Number for f32 {
+    fn from_f64(v: f64) -> Self {
+        v as f32
+    }
+}
+
+impl Number for f64 {
+    fn from_f64(v: f64) -> Self {
+        v
+    }
+}
+
+/// `section.key` → (value, line number) for one parsed file. Typed getters
+/// remove the keys they read, so whatever is left over at the end is unknown.
+///
+/// The `n`th `[[name]]` table of an array of tables is the section `name.n`.
+pub struct Document {
+    entries: BTreeMap<String, (Value, usize)>,
+    /// Number of `[[name]]` tables by name.
+    tables: BTreeMap<String, usize>,
+}
+
+impl Document {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut entries = BTreeMap::new();
+        let mut tables = BTreeMap::new();
+        let mut section = String::new();
+
+        for (index, raw) in text.lines().enumerate() {
+            let line_no = index + 1;
+            let line = strip_comment(raw).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix("[[") {
+                let name = name
+                    .strip_suffix("]]")
+                    .ok_or_else(|| format!("line {}: expected ']]'", line_no))?
+                    .trim()
+                    .to_string();
+                let count = tables.entry(name.clone()).or_insert(0);
+                section = format!("{}.{}", name, count);
+                *count += 1;
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                section = name
+                    .strip_suffix(']')
+                    .ok_or_else(|| format!("line {}: expected ']'", line_no))?
+                    .trim()
+                    .to_string();
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or_else(|| format!("line {}: expected 'key = value'", line_no))?;
+            let value = parse_value(value.trim()).ok_or_else(|| format!("line {}: invalid value '{}'", line_no, value.trim()))?;
+            let key = format!("{}.{}", section, key.trim());
+            if entries.insert(key.clone(), (value, line_no)).is_some() {
+                return Err(format!("line {}: duplicate key {}", line_no, key));
+            }
+        }
+        Ok(Self { entries, tables })
+    }
+
+    /// Number of `[[name]]` tables in the file.
+    pub fn table_count(&self, name: &str) -> usize {
+        self.tables.get(name).copied().unwrap_or(0)
+    }
+
+    /// Take out the keys of `section`, as a document of their own with the
+    /// section name dropped from the keys.
+    pub fn split_off(&mut self, section: &str) -> Document {
+        let prefix = format!("{}.", section);
+        let keys: Vec<String> = self.entries.keys().filter(|key| key.starts_with(&prefix)).cloned().collect();
+        let entries = keys
+            .into_iter()
+            .filter_map(|key| {
+                let entry = self.entries.remove(&key)?;
+                Some((key[prefix.len()..].to_string(), entry))
+            })
+            .collect();
+        Document { entries, tables: BTreeMap::new() }
+    }
+
+    pub fn number<T: Number>(&mut self, key: &str, out: &mut T) -> Result<(), String> {
+        match self.entries.remove(key) {
+            None => Ok(()),
+            Some((Value::Number(v), _)) => {
+                *out = T::from_f64(v);
+                Ok(())
+            }
+            Some((_, line)) => Err(format!("line {}: {} must be a number", line, key)),
+        }
+    }
+
+    pub fn boolean(&mut self, key: &str, out: &mut bool) -> Result<(), String> {
+        match self.entries.remove(key) {
+            None => Ok(()),
+            Some((Value::Bool(v), _)) => {
+                *out = v;
+                Ok(())
+            }
+            Some((_, line)) => Err(format!("line {}: {} must be true or false", line, key)),
+        }
+    }
+
+    pub fn text(&mut self, key: &str) -> Result<Option<String>, String> {
+        match self.entries.remove(key) {
+            None => Ok(None),
+            Some((Value::Text(v), _)) => Ok(Some(v)),
+            Some((_, line)) => Err(format!("line {}: {} must be a string", line, key)),
+        }
+    }
+
+    pub fn vec3(&mut self, key: &str, out: &mut glm::Vec3) -> Result<(), String> {
+        match self.entries.remove(key) {
+            None => Ok(()),
+            Some((Value::Array(v), _)) if v.len() == 3 => {
+                *out = glm::vec3(v[0] as f32, v[1] as f32, v[2] as f32);
+                Ok(())
+            }
+            Some((_, line)) => Err(format!("line {}: {} must be an array of 3 numbers", line, key)),
+        }
+    }
+
+    /// Fail on any key no getter asked for, which is most likely a typo.
+    pub fn finish(self) -> Result<(), String> {
+        match self.entries.iter().min_by_key(|(_, (_, line))| *line) {
+            Some((key, (_, line))) => Err(format!("line {}: unknown key {}", line, key)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Drop a trailing `# comment` that is not inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(raw: &str) -> Option<Value> {
+    match raw {
+        "true" => return Some(Value::Bool(true)),
+        "false" => return Some(Value::Bool(false)),
+        _ => {}
+    }
+    if let Some(text) = raw.strip_prefix('"').and_then(|r| r.strip_suffix('"')) {
+        return Some(Value::Text(text.to_string()));
+    }
+    if let Some(items) = raw.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+        return items
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| item.parse().ok())
+            .collect::<Option<Vec<f64>>>()
+            .map(Value::Array);
+    }
+    raw.parse().ok().map(Value::Number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::exposure::Tonemap;
+
+    #[test]
+    fn round_trips_through_toml() {
+        let mut scene = Scene {
+            camera_mode: CameraMode::TopView,
+            camera_type: CameraType::FreeCam,
+            radius: 23.5,
+            azimuth: -12.25,
+            roll: 3.0,
+            position: glm::vec3(1.0, -2.5, 0.1),
+            fov: 42.0,
+            render_disk: false,
+            redshift: false,
+            emission_mode: EmissionMode::Blackbody,
+            gravitational_lensing: false,
+            quality: Quality::Ultra,
+            time: 12.3,
+            ..Scene::default()
+        };
+        scene.black_hole.mass = 6.5e9;
+        scene.black_hole.spin = -0.7;
+        scene.disk.isco = false;
+        scene.disk.outer = 20.0;
+        scene.exposure.tonemap = Tonemap::Aces;
+        scene.exposure.ev = -1.5;
+        scene.bloom.enabled = false;
+
+        let text = scene.to_toml();
+        let parsed = Scene::parse(&text).unwrap();
+        assert_eq!(parsed.to_toml(), text);
+        assert_eq!(parsed.position, scene.position);
+        assert_eq!(parsed.black_hole.mass, 6.5e9);
+    }
+
+    #[test]
+    fn missing_keys_keep_their_default() {
+        let scene = Scene::parse("# just the spin\n[black_hole]\nspin = 0.5 # prograde\n").unwrap();
+        assert_eq!(scene.black_hole.spin, 0.5);
+        assert_eq!(scene.to_toml(), Scene { black_hole: scene.black_hole, ..Scene::default() }.to_toml());
+    }
+
+    #[test]
+    fn rejects_unknown_keys_with_their_line() {
+        let error = Scene::parse("[camera]\nfov = 50.0\nazimut = 10.0\n").err().unwrap();
+        assert_eq!(error, "line 3: unknown key camera.azimut");
+        assert!(Scene::parse("[camra]\nfov = 50.0\n").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        assert!(Scene::parse("[render]\nlensing = 1\n").is_err());
+        assert!(Scene::parse("[render]\nquality = \"extreme\"\n").is_err());
+        assert!(Scene::parse("[black_hole]\nspin = 1.0\n").is_err());
+        assert!(Scene::parse("[black_hole]\nspin = nan\n").is_err());
+        assert!(Scene::parse("[black_hole]\nmass = 0.0\n").is_err());
+        assert!(Scene::parse("[black_hole]\nmass = nan\n").is_err());
+        assert!(Scene::parse("[black_hole]\nmass = inf\n").is_err());
+        assert!(Scene::parse("[camera]\nfov = 0.0\n").is_err());
+        assert!(Scene::parse("[camera]\nfov = 200.0\n").is_err());
+        assert!(Scene::parse("[camera]\nfov = nan\n").is_err());
+        assert!(Scene::parse("[camera]\nradius = inf\n").is_err());
+        assert!(Scene::parse("[camera]\nazimuth = nan\n").is_err());
+        assert!(Scene::parse("[camera]\nelevation = -inf\n").is_err());
+        assert!(Scene::parse("[camera]\nroll = nan\n").is_err());
+        assert!(Scene::parse("[camera]\nposition = [0.0, nan, 1.0]\n").is_err());
+        assert!(Scene::parse("[disk]\ninner = 10.0\nouter = 8.0\n").is_err());
+        assert!(Scene::parse("[camera]\nfov = 50.0\nfov = 60.0\n").is_err());
+    }
+}
@@ -1,13 +1,104 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::ffi::CString;
+use std::path::{Path, PathBuf};
 use std::ptr;
+use std::time::{Duration, Instant, SystemTime};
+
+use gl::types::{GLchar, GLenum, GLint, GLuint};
+use nalgebra_glm as glm;
 
 use crate::gl_bindings::*;
 
-pub fn load_shader(path: &str, shader_type: u32) -> Result<u32, String> {
-    let source = std::fs::read_to_string(path)
-        .map_err(|e| format!("Failed to read shader file {}: {}", path, e))?;
+/// Line numbers of the `n`th file of a shader start at `n * FILE_LINES + 1`.
+/// Mesa reports source string 0 for most errors whatever `#line` said, so
+/// the line number alone has to tell the files apart.
+const FILE_LINES: u32 = 100_000;
+
+/// Macro injected into a shader as `#define NAME VALUE`.
+pub type Define = (String, String);
+
+/// Source of a shader with its includes resolved, ready for the compiler.
+pub struct ShaderSource {
+    pub text: String,
+    /// Every file that went into `text`, the shader itself first. A file's
+    /// index is its source string number in the `#line` directives.
+    pub files: Vec<PathBuf>,
+}
+
+/// Read the shader at `path`, replacing every `#include "file"` line by
+/// that file, resolved against the directory of the file naming it. Each
+/// file goes in once per shader however often it is included, so shared
+/// files need no include guards of their own. `defines` are inserted after
+/// the `#version` line, and `#line` directives let `map_log` trace the
+/// compiler's line numbers back to the original files.
+pub fn preprocess(path: &Path, defines: &[Define]) -> Result<ShaderSource, String> {
+    let mut source = ShaderSource { text: String::new(), files: Vec::new() };
+    let mut included = HashSet::new();
+    expand(path, defines, &mut source, &mut included)?;
+    Ok(source)
+}
+
+fn expand(path: &Path, defines: &[Define], source: &mut ShaderSource, included: &mut HashSet<PathBuf>) -> Result<(), String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read shader file {}: {}", path.display(), e))?;
+    included.insert(path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+    let index = source.files.len();
+    source.files.push(path.to_path_buf());
+
+    let lines: Vec<&str> = text.lines().collect();
+    let version = lines.iter().position(|line| line.trim_start().starts_with("#version"));
+    if index > 0 {
+        if let Some(n) = version {
+            return Err(format!("{}:{}: #version is only allowed in the shader itself", path.display(), n + 1));
+        }
+        source.text.push_str(&line_directive(index, 1));
+    } else if version.is_none() && !defines.is_empty() {
+        push_defines(&mut source.text, defines);
+        source.text.push_str(&line_directive(index, 1));
+    }
+
+    for (n, line) in lines.iter().enumerate() {
+        let Some(directive) = line.trim_start().strip_prefix("#include") else {
+            source.text.push_str(line);
+            source.text.push('\n');
+            if index == 0 && Some(n) == version && !defines.is_empty() {
+                push_defines(&mut source.text, defines);
+                source.text.push_str(&line_directive(index, n + 2));
+            }
+            continue;
+        };
+
+        let name = directive
+            .trim()
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+            .ok_or_else(|| format!("{}:{}: expected #include \"file\"", path.display(), n + 1))?;
+        let file = path.parent().unwrap_or(Path::new("")).join(name);
+        if !included.contains(&file.canonicalize().unwrap_or_else(|_| file.clone())) {
+            expand(&file, defines, source, included)
+                .map_err(|e| format!("{}\n  included from {}:{}", e, path.display(), n + 1))?;
+        }
+        source.text.push_str(&line_directive(index, n + 2));
+    }
+    Ok(())
+}
+
+/// `#line` directive making the next line `line` of the `index`th file.
+fn line_directive(index: usize, line: usize) -> String {
+    format!("#line {} {}\n", index as u32 * FILE_LINES + line as u32, index)
+}
+
+fn push_defines(text: &mut String, defines: &[Define]) {
+    for (name, value) in defines {
+        text.push_str(&format!("#define {} {}\n", name, value));
+    }
+}
+
+pub fn load_shader(path: &str, shader_type: u32, defines: &[Define]) -> Result<u32, String> {
+    let source = preprocess(Path::new(path), defines)?;
     
-    let c_source = CString::new(source.as_bytes())
+    let c_source = CString::new(source.text.as_bytes())
         .map_err(|e| format!("CString conversion failed: {}", e))?;
     
     unsafe {
@@ -24,22 +115,33 @@ pub fn load_shader(path: &str, shader_type: u32) -> Result<u32, String> {
             let mut buffer = vec![0u8; len as usize];
             GetShaderInfoLog(shader, len, ptr::null_mut(), buffer.as_mut_ptr() as *mut i8);
             let error = String::from_utf8_lossy(&buffer);
-            return Err(format!("Shader compilation failed for {}: {}", path, error));
+            DeleteShader(shader);
+            return Err(format!("Shader compilation failed for {}:\n{}", path, map_log(&error, &source.files)));
         }
         
         Ok(shader)
     }
 }
 
-pub fn create_shader_program(vert_path: &str, frag_path: &str) -> Result<u32, String> {
-    let vert_shader = load_shader(vert_path, VERTEX_SHADER)?;
-    let frag_shader = load_shader(frag_path, FRAGMENT_SHADER)?;
+/// Compile and link a program from a vertex and a fragment shader, with
+/// `defines` injected into both.
+pub fn create_shader_program(vert_path: &str, frag_path: &str, defines: &[Define]) -> Result<u32, String> {
+    let vert_shader = load_shader(vert_path, VERTEX_SHADER, defines)?;
+    let frag_shader = match load_shader(frag_path, FRAGMENT_SHADER, defines) {
+        Ok(shader) => shader,
+        Err(e) => {
+            unsafe { DeleteShader(vert_shader) };
+            return Err(e);
+        }
+    };
     
     unsafe {
         let program = CreateProgram();
         AttachShader(program, vert_shader);
         AttachShader(program, frag_shader);
         LinkProgram(program);
+        DeleteShader(vert_shader);
+        DeleteShader(frag_shader);
         
         let mut success = 0;
         GetProgramiv(program, LINK_STATUS, &mut success);
@@ -50,12 +152,360 @@ pub fn create_shader_program(vert_path: &str, frag_path: &str) -> Result<u32, St
             let mut buffer = vec![0u8; len as usize];
             GetProgramInfoLog(program, len, ptr::null_mut(), buffer.as_mut_ptr() as *mut i8);
             let error = String::from_utf8_lossy(&buffer);
+            DeleteProgram(program);
             return Err(format!("Program linking failed: {}", error));
         }
         
-        DeleteShader(vert_shader);
-        DeleteShader(frag_shader);
-        
         Ok(program)
     }
-}
\ No newline at end of file
+}
+/// Linked program that looked up its active uniforms once, so setting one
+/// needs no call into the driver to find it. The setters work on the bound
+/// program. A uniform the program lacks, or declares with another type, is
+/// skipped with a warning the first time; the compiler drops uniforms the
+/// shader does not use, which is common while one is being edited.
+pub struct ShaderProgram {
+    pub id: GLuint,
+    /// Shown in warnings.
+    name: String,
+    /// Location and GL type of each active uniform.
+    uniforms: HashMap<String, (GLint, GLenum)>,
+    /// Uniforms already warned about.
+    warned: RefCell<HashSet<String>>,
+}
+
+impl ShaderProgram {
+    pub fn new(vert_path: &str, frag_path: &str) -> Result<Self, String> {
+        Self::with_defines(vert_path, frag_path, &[])
+    }
+
+    /// Program built by `create_shader_program`.
+    pub fn with_defines(vert_path: &str, frag_path: &str, defines: &[Define]) -> Result<Self, String> {
+        let id = create_shader_program(vert_path, frag_path, defines)?;
+        let mut uniforms = HashMap::new();
+        unsafe {
+            let (mut count, mut max_length) = (0, 0);
+            GetProgramiv(id, ACTIVE_UNIFORMS, &mut count);
+            GetProgramiv(id, ACTIVE_UNIFORM_MAX_LENGTH, &mut max_length);
+            for index in 0..count as GLuint {
+                let mut buffer = vec![0u8; max_length.max(1) as usize];
+                let (mut length, mut size, mut kind) = (0, 0, 0);
+                GetActiveUniform(id, index, max_length, &mut length, &mut size, &mut kind, buffer.as_mut_ptr() as *mut GLchar);
+                buffer.truncate(length as usize);
+                let Ok(c_name) = CString::new(buffer) else {
+                    continue;
+                };
+                // built-in gl_ uniforms have no location
+                let location = GetUniformLocation(id, c_name.as_ptr());
+                if location >= 0 {
+                    // arrays are listed by their first element
+                    let name = c_name.to_string_lossy();
+                    uniforms.insert(name.strip_suffix("[0]").unwrap_or(&name).to_string(), (location, kind));
+                }
+            }
+        }
+        Ok(Self { id, name: frag_path.to_string(), uniforms, warned: RefCell::new(HashSet::new()) })
+    }
+
+    pub fn bind(&self) {
+        unsafe { UseProgram(self.id) };
+    }
+
+    pub fn set_f32(&self, name: &str, value: f32) {
+        if let Some(location) = self.location(name, &[FLOAT]) {
+            unsafe { Uniform1f(location, value) };
+        }
+    }
+
+    pub fn set_i32(&self, name: &str, value: i32) {
+        if let Some(location) = self.location(name, &[INT]) {
+            unsafe { Uniform1i(location, value) };
+        }
+    }
+
+    pub fn set_bool(&self, name: &str, value: bool) {
+        if let Some(location) = self.location(name, &[BOOL]) {
+            unsafe { Uniform1i(location, value as i32) };
+        }
+    }
+
+    pub fn set_vec2(&self, name: &str, value: glm::Vec2) {
+        if let Some(location) = self.location(name, &[FLOAT_VEC2]) {
+            unsafe { Uniform2f(location, value.x, value.y) };
+        }
+    }
+
+    pub fn set_vec3(&self, name: &str, value: &glm::Vec3) {
+        if let Some(location) = self.location(name, &[FLOAT_VEC3]) {
+            unsafe { Uniform3f(location, value.x, value.y, value.z) };
+        }
+    }
+
+    pub fn set_vec4(&self, name: &str, value: &glm::Vec4) {
+        if let Some(location) = self.location(name, &[FLOAT_VEC4]) {
+            unsafe { Uniform4f(location, value.x, value.y, value.z, value.w) };
+        }
+    }
+
+    pub fn set_mat3(&self, name: &str, value: &glm::Mat3) {
+        if let Some(location) = self.location(name, &[FLOAT_MAT3]) {
+            unsafe { UniformMatrix3fv(location, 1, FALSE, value.as_ptr()) };
+        }
+    }
+
+    /// Make the sampler `name` read from texture unit `unit`.
+    pub fn set_sampler(&self, name: &str, unit: u32) {
+        if let Some(location) = self.location(name, &[SAMPLER_2D, SAMPLER_CUBE]) {
+            unsafe { Uniform1i(location, unit as i32) };
+        }
+    }
+
+    /// Location of the uniform `name` if it is active and of one of `types`.
+    fn location(&self, name: &str, types: &[GLenum]) -> Option<GLint> {
+        let found = self.uniforms.get(name);
+        if let Some(&(location, _)) = found.filter(|(_, kind)| types.contains(kind)) {
+            return Some(location);
+        }
+        if self.warned.borrow_mut().insert(name.to_string()) {
+            match found {
+                Some(&(_, kind)) => eprintln!(
+                    "WARNING: {}: uniform {} is a {}, not a {}",
+                    self.name,
+                    name,
+                    type_name(kind),
+                    type_name(types[0])
+                ),
+                None => eprintln!("WARNING: {}: no active uniform {}", self.name, name),
+            }
+        }
+        None
+    }
+}
+
+impl Drop for ShaderProgram {
+    fn drop(&mut self) {
+        unsafe { DeleteProgram(self.id) };
+    }
+}
+
+/// GLSL name of a uniform type.
+fn type_name(kind: GLenum) -> String {
+    match kind {
+        FLOAT => "float".to_string(),
+        INT => "int".to_string(),
+        BOOL => "bool".to_string(),
+        FLOAT_VEC2 => "vec2".to_string(),
+        FLOAT_VEC3 => "vec3".to_string(),
+        FLOAT_VEC4 => "vec4".to_string(),
+        FLOAT_MAT3 => "mat3".to_string(),
+        FLOAT_MAT4 => "mat4".to_string(),
+        SAMPLER_2D => "sampler2D".to_string(),
+        SAMPLER_CUBE => "samplerCube".to_string(),
+        _ => format!("type 0x{:x}", kind),
+    }
+}
+
+/// Rewrite the locations in a compiler log as `path:line:`, so they point
+/// into the file like other compilers do. `files` are those of the shader
+/// as numbered by `preprocess`.
+/// Drivers differ: Mesa writes `0:12(5): error: ...`, NVIDIA
+/// `0(12) : error C1008: ...` and AMD and Intel `ERROR: 0:12: ...`. Lines
+/// in other formats are kept as they are.
+pub fn map_log(log: &str, files: &[PathBuf]) -> String {
+    log.lines()
+        .map(str::trim_end)
+        .filter(|line| !line.trim_matches('\0').is_empty())
+        .map(|line| match locate(line) {
+            Some((line_no, message)) if ((line_no / FILE_LINES) as usize) < files.len() => {
+                let file = &files[(line_no / FILE_LINES) as usize];
+                format!("{}:{}: {}", file.display(), line_no % FILE_LINES, message)
+            }
+            _ => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Line number and message of a located compiler log line.
+fn locate(line: &str) -> Option<(u32, String)> {
+    let (severity, rest) = match line.split_once(": ") {
+        Some((severity @ ("ERROR" | "WARNING"), rest)) => (Some(severity.to_lowercase()), rest),
+        _ => (None, line),
+    };
+    let digits = |s: &str| s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let source = digits(rest);
+    if source == 0 {
+        return None;
+    }
+    let rest = &rest[source..];
+
+    // `:12(5):` and `:12:`, or `(12) :`
+    let (line_no, rest) = if let Some(rest) = rest.strip_prefix(':') {
+        let n = digits(rest);
+        let (line_no, mut rest) = rest.split_at(n);
+        if let Some(column) = rest.strip_prefix('(') {
+            rest = &column[column.find(')')? + 1..];
+        }
+        (line_no, rest.trim_start().strip_prefix(':')?)
+    } else {
+        let rest = rest.strip_prefix('(')?;
+        let n = digits(rest);
+        let (line_no, rest) = rest.split_at(n);
+        (line_no, rest.strip_prefix(')')?.trim_start().strip_prefix(':')?)
+    };
+    let line_no = line_no.parse().ok()?;
+    let message = rest.trim();
+    Some(match severity {
+        Some(severity) => (line_no, format!("{}: {}", severity, message)),
+        None => (line_no, message.to_string()),
+    })
+}
+
+/// Time between two looks at the watched files.
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Notices when shader sources change on disk, by polling their modification
+/// times. A change is reported once the files have stayed the same for one
+/// interval, so editors that save in several steps trigger a single reload.
+/// Files the shaders include are watched along with them.
+pub struct ShaderWatcher {
+    shaders: Vec<PathBuf>,
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    last_poll: Instant,
+    /// Whether a change was seen that has not been reported yet.
+    pending: bool,
+}
+
+impl ShaderWatcher {
+    pub fn new<I: IntoIterator<Item = PathBuf>>(paths: I) -> Self {
+        let mut watcher = Self { shaders: paths.into_iter().collect(), files: Vec::new(), last_poll: Instant::now(), pending: false };
+        watcher.files = watcher.sources().into_iter().map(|path| {
+            let modified = modified(&path);
+            (path, modified)
+        }).collect();
+        watcher
+    }
+
+    /// Whether any file changed since the last reported change.
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < WATCH_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = false;
+        for (path, stamp) in &mut self.files {
+            let modified = modified(path);
+            if modified != *stamp {
+                *stamp = modified;
+                changed = true;
+            }
+        }
+        if changed {
+            // the edit may have added or dropped includes
+            for path in self.sources() {
+                if !self.files.iter().any(|(file, _)| *file == path) {
+                    let modified = modified(&path);
+                    self.files.push((path, modified));
+                }
+            }
+            self.pending = true;
+            return false;
+        }
+        std::mem::take(&mut self.pending)
+    }
+
+    /// The shaders and every file they include, as far as they can be read.
+    fn sources(&self) -> Vec<PathBuf> {
+        let mut sources = Vec::new();
+        for shader in &self.shaders {
+            let files = preprocess(shader, &[]).map(|source| source.files).unwrap_or_else(|_| vec![shader.clone()]);
+            for file in files {
+                if !sources.contains(&file) {
+                    sources.push(file);
+                }
+            }
+        }
+        sources
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write `files` into a fresh directory under the system temp dir.
+    fn shader_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("blackhole-shader-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, text) in files {
+            std::fs::write(dir.join(file), text).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn includes_are_pasted_once_with_line_directives() {
+        let dir = shader_dir(
+            "include",
+            &[
+                ("main.frag", "#version 330 core\n#include \"a.glsl\"\n#include \"b.glsl\"\nvoid main() {}\n"),
+                ("a.glsl", "float a() { return 1.0; }\n"),
+                ("b.glsl", "#include \"a.glsl\"\nfloat b() { return a(); }\n"),
+            ],
+        );
+        let defines = vec![("ADISK_LIT".to_string(), "2.0".to_string())];
+        let source = preprocess(&dir.join("main.frag"), &defines).unwrap();
+
+        assert_eq!(source.files, vec![dir.join("main.frag"), dir.join("a.glsl"), dir.join("b.glsl")]);
+        assert_eq!(source.text.matches("float a()").count(), 1);
+        assert_eq!(
+            source.text,
+            "#version 330 core\n#define ADISK_LIT 2.0\n#line 2 0\n\
+             #line 100001 1\nfloat a() { return 1.0; }\n#line 3 0\n\
+             #line 200001 2\n#line 200002 2\nfloat b() { return a(); }\n#line 4 0\n\
+             void main() {}\n"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn preprocess_rejects_version_in_includes_and_bad_syntax() {
+        let dir = shader_dir(
+            "reject",
+            &[
+                ("main.frag", "#version 330 core\n#include \"a.glsl\"\n"),
+                ("a.glsl", "#version 330 core\n"),
+                ("bad.frag", "#version 330 core\n#include <a.glsl>\n"),
+            ],
+        );
+        let err = preprocess(&dir.join("main.frag"), &[]).err().unwrap();
+        assert!(err.contains("a.glsl:1: #version is only allowed"), "{}", err);
+        assert!(err.contains("included from"), "{}", err);
+        let err = preprocess(&dir.join("bad.frag"), &[]).err().unwrap();
+        assert!(err.contains("bad.frag:2: expected #include"), "{}", err);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn map_log_understands_vendor_formats() {
+        let files = vec![PathBuf::from("main.frag"), PathBuf::from("common/noise.glsl")];
+        // Mesa, NVIDIA and AMD
+        assert_eq!(map_log("0:100123(4): error: `x' undeclared", &files), "common/noise.glsl:123: error: `x' undeclared");
+        assert_eq!(map_log("0(123) : error C1008: undefined variable \"x\"", &files), "main.frag:123: error C1008: undefined variable \"x\"");
+        assert_eq!(map_log("ERROR: 0:100123: 'x' : undeclared identifier", &files), "common/noise.glsl:123: error: 'x' : undeclared identifier");
+        assert_eq!(map_log("WARNING: 0:7: unused", &files), "main.frag:7: warning: unused");
+    }
+
+    #[test]
+    fn map_log_keeps_lines_it_cannot_place() {
+        let files = vec![PathBuf::from("main.frag")];
+        assert_eq!(map_log("0:200001(1): error: out of range", &files), "0:200001(1): error: out of range");
+        assert_eq!(map_log("link failed\n\0", &files), "link failed");
+    }
+}
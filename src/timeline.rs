@@ -0,0 +1,427 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
+
+use crate::cli::{Backend, Options};
+use crate::metadata::shader_hash;
+use crate::renderer::app::{App, MAX_SPIN};
+use crate::renderer::cpu::CpuRenderer;
+use crate::renderer::disk::DiskParam;
+use crate::renderer::frame::FrameParams;
+use crate::renderer::poster::report_progress;
+use crate::renderer::recorder::{RecordTarget, Y4mWriter};
+use crate::scene::{Document, Scene};
+
+/// Frame rate of an export when the timeline file does not give one.
+pub const DEFAULT_FPS: f64 = 30.0;
+
+/// Numbers of a scene that keyframes interpolate with their ease, besides
+/// the disk parameters and `time`, which always runs on linearly. Everything
+/// else switches at the keyframe that changes it.
+const ANIMATED: [fn(&mut Scene) -> &mut f32; 11] = [
+    |s| &mut s.radius,
+    |s| &mut s.azimuth,
+    |s| &mut s.elevation,
+    |s| &mut s.roll,
+    |s| &mut s.position.x,
+    |s| &mut s.position.y,
+    |s| &mut s.position.z,
+    |s| &mut s.fov,
+    |s| &mut s.exposure.ev,
+    |s| &mut s.bloom.threshold,
+    |s| &mut s.bloom.intensity,
+];
+const ANIMATED_F64: [fn(&mut Scene) -> &mut f64; 2] = [|s| &mut s.black_hole.mass, |s| &mut s.black_hole.spin];
+
+/// How a keyframe's values move on towards the next keyframe.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Ease {
+    /// Smooth curve through all keyframes, without stopping at them.
+    CatmullRom,
+    Linear,
+    /// Starts and stops gently at both keyframes.
+    InOut,
+    /// Keeps the values until the next keyframe.
+    Hold,
+}
+
+impl Ease {
+    /// Value a fraction `s` of the way from `values[1]` to `values[2]`,
+    /// where `values` are taken at four consecutive keyframes at `times`.
+    pub fn interpolate(self, values: [f64; 4], times: [f64; 4], s: f64) -> f64 {
+        let [p0, p1, p2, p3] = values;
+        match self {
+            Ease::CatmullRom => {
+                // tangents scaled to the length of the segment, so unevenly
+                // spaced keyframes do not make the speed jump at them
+                let duration = times[2] - times[1];
+                let m1 = (p2 - p0) / (times[2] - times[0]) * duration;
+                let m2 = (p3 - p1) / (times[3] - times[1]) * duration;
+                let (s2, s3) = (s * s, s * s * s);
+                (2.0 * s3 - 3.0 * s2 + 1.0) * p1 + (s3 - 2.0 * s2 + s) * m1 + (3.0 * s2 - 2.0 * s3) * p2 + (s3 - s2) * m2
+            }
+            Ease::Linear => p1 + (p2 - p1) * s,
+            Ease::InOut => p1 + (p2 - p1) * s * s * (3.0 - 2.0 * s),
+            Ease::Hold => p1,
+        }
+    }
+}
+
+impl FromStr for Ease {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "catmull_rom" => Ok(Ease::CatmullRom),
+            "linear" => Ok(Ease::Linear),
+            "ease_in_out" => Ok(Ease::InOut),
+            "hold" => Ok(Ease::Hold),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The whole scene at one point of an animation.
+#[derive(Clone)]
+pub struct Keyframe {
+    /// Seconds from the start of the animation.
+    pub time: f64,
+    pub scene: Scene,
+    /// Curve towards the next keyframe.
+    pub ease: Ease,
+}
+
+/// Camera and scene animation for `--timeline` exports. Stored as a scene
+/// file with one `[[keyframe]]` table per keyframe, whose keys are the scene
+/// keys written with their section:
+///
+/// ```toml
+/// [timeline]
+/// fps = 30.0
+/// scene = "start.toml"
+///
+/// [[keyframe]]
+/// time = 0.0
+/// camera.azimuth = 0.0
+/// camera.radius = 30.0
+///
+/// [[keyframe]]
+/// time = 8.0
+/// ease = "ease_in_out"
+/// camera.azimuth = 180.0
+/// camera.radius = 12.0
+/// black_hole.spin = 0.9
+/// ```
+///
+/// The first keyframe starts from `scene`, a scene file relative to the
+/// timeline, or the default scene; later ones start from the keyframe before
+/// them. Keys a keyframe leaves out keep their value, except `render.time`,
+/// which runs on with the animation so the disk keeps turning.
+///
+/// The camera's azimuth and roll turn the short way from one keyframe to the
+/// next, so 350 to 10 degrees is a 20 degree turn; going further than half a
+/// turn takes keyframes in between.
+pub struct Timeline {
+    pub fps: f64,
+    /// In order of time.
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl Timeline {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Self::parse(&text, dir).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Parse a timeline file, resolving the path of its `scene` against `dir`.
+    pub fn parse(text: &str, dir: &Path) -> Result<Self, String> {
+        let mut doc = Document::parse(text)?;
+        let mut fps = DEFAULT_FPS;
+        doc.number("timeline.fps", &mut fps)?;
+        if !(fps.is_finite() && fps > 0.0) {
+            return Err("timeline.fps must be positive".to_string());
+        }
+        let mut scene = match doc.text("timeline.scene")? {
+            Some(path) => Scene::load(&dir.join(path))?,
+            None => Scene::default(),
+        };
+
+        let mut keyframes: Vec<Keyframe> = Vec::new();
+        for i in 0..doc.table_count("keyframe") {
+            let mut table = doc.split_off(&format!("keyframe.{}", i));
+            let fail = |e: String| format!("keyframe {}: {}", i + 1, e);
+            let mut time = f64::NAN;
+            table.number("time", &mut time).map_err(fail)?;
+            if time.is_nan() {
+                return Err(fail("time is missing".to_string()));
+            }
+            if time < 0.0 {
+                return Err(fail("time must not be negative".to_string()));
+            }
+            if let Some(previous) = keyframes.last() {
+                if time <= previous.time {
+                    return Err(fail("time must be later than the keyframe before".to_string()));
+                }
+                scene.time += (time - previous.time) as f32;
+            }
+            let ease = match table.text("ease").map_err(fail)? {
+                Some(ease) => ease.parse().map_err(|_| fail(format!("unknown ease \"{}\"", ease)))?,
+                None => Ease::CatmullRom,
+            };
+            scene = Scene::read(&mut table, scene).map_err(fail)?;
+            if let Some(previous) = keyframes.last() {
+                scene.azimuth = nearest_turn(scene.azimuth, previous.scene.azimuth);
+                scene.roll = nearest_turn(scene.roll, previous.scene.roll);
+            }
+            table.finish()?;
+            keyframes.push(Keyframe { time, scene: scene.clone(), ease });
+        }
+        doc.finish()?;
+
+        if keyframes.is_empty() {
+            return Err("a timeline needs at least one [[keyframe]]".to_string());
+        }
+        Ok(Self { fps, keyframes })
+    }
+
+    /// Time of the last keyframe, where the animation ends.
+    pub fn duration(&self) -> f64 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// Frames of an export, from time 0 up to and including the last keyframe.
+    pub fn frame_count(&self) -> u32 {
+        // the tolerance keeps a last keyframe on a frame from being lost to rounding
+        (self.duration() * self.fps + 1e-6).floor() as u32 + 1
+    }
+
+    /// The scene at `time` seconds into the animation. Before the first
+    /// keyframe and after the last one the scene stands still.
+    pub fn sample(&self, time: f64) -> Scene {
+        let keys = &self.keyframes;
+        let next = keys.partition_point(|keyframe| keyframe.time <= time);
+        if next == 0 {
+            return keys[0].scene.clone();
+        }
+        if next == keys.len() {
+            return keys[next - 1].scene.clone();
+        }
+
+        let i = next - 1;
+        // the first and last keyframes stand in for the missing neighbours at the ends
+        let indices = [i.saturating_sub(1), i, i + 1, (i + 2).min(keys.len() - 1)];
+        let mut around = indices.map(|k| keys[k].scene.clone());
+        let times = indices.map(|k| keys[k].time);
+        let s = (time - times[1]) / (times[2] - times[1]);
+        let curve = |values: [f64; 4]| keys[i].ease.interpolate(values, times, s);
+
+        let mut scene = keys[i].scene.clone();
+        for field in ANIMATED {
+            *field(&mut scene) = curve(around.each_mut().map(|k| *field(k) as f64)) as f32;
+        }
+        for field in ANIMATED_F64 {
+            *field(&mut scene) = curve(around.each_mut().map(|k| *field(k)));
+        }
+        // the disk clock ignores the ease, so holds and ease-ins do not stop the disk
+        scene.time = Ease::Linear.interpolate(around.each_ref().map(|k| k.time as f64), times, s) as f32;
+        for param in DiskParam::ALL {
+            let (min, max, _) = param.range();
+            let value = curve(around.each_ref().map(|k| k.disk.get(param) as f64)) as f32;
+            *scene.disk.get_mut(param) = value.clamp(min, max);
+        }
+
+        // keep curves that swing past the keyframes within what is valid
+        scene.black_hole.spin = scene.black_hole.spin.clamp(-MAX_SPIN, MAX_SPIN);
+        let (a, b) = (around[1].black_hole.mass, around[2].black_hole.mass);
+        scene.black_hole.mass = scene.black_hole.mass.max(a.min(b));
+        scene.bloom.threshold = scene.bloom.threshold.max(0.0);
+        scene
+    }
+}
+
+/// `angle` in degrees, moved by whole turns to within half a turn of
+/// `reference`.
+fn nearest_turn(angle: f32, reference: f32) -> f32 {
+    let difference = angle - reference;
+    if difference.abs() <= 180.0 {
+        return angle;
+    }
+    angle - 360.0 * (difference / 360.0).round()
+}
+
+/// Path of frame `frame` of an export to `pattern`: a run of `#` in the file
+/// name is replaced by the zero-padded frame number, and without one the
+/// number is added to the end of the name.
+pub fn frame_path(pattern: &Path, frame: u32) -> PathBuf {
+    let name = pattern.file_name().unwrap_or_default().to_string_lossy();
+    let name = match name.find('#') {
+        Some(start) => {
+            let digits = name[start..].chars().take_while(|&c| c == '#').count();
+            format!("{}{:0width$}{}", &name[..start], frame, &name[start + digits..], width = digits)
+        }
+        None => {
+            let stem = pattern.file_stem().unwrap_or_default().to_string_lossy();
+            match pattern.extension() {
+                Some(ext) => format!("{}_{:05}.{}", stem, frame, ext.to_string_lossy()),
+                None => format!("{}_{:05}", stem, frame),
+            }
+        }
+    };
+    pattern.with_file_name(name)
+}
+
+/// Render every frame of `timeline` offscreen with the `--headless` backend
+/// and size, to the numbered paths `frame_path` makes of `--output`, or as
+/// one YUV4MPEG2 stream to a `.y4m` output, `--record` or `--record-pipe`.
+/// Frame `n` shows the animation at `n / fps` seconds however long it takes
+/// to draw, and the view options on the command line override every frame.
+pub fn export(timeline: &Timeline, options: &Options) -> Result<(), String> {
+    let (width, height) = (options.width, options.height);
+    let hash = shader_hash(&options.vertex_shader, &options.fragment_shader, &options.defines);
+    let mut app = match options.backend {
+        Backend::Gpu => Some(App::headless(options, &timeline.keyframes[0].scene)?),
+        Backend::Cpu => None,
+    };
+    let cpu = match options.backend {
+        Backend::Gpu => None,
+        Backend::Cpu => Some(CpuRenderer::load(&options.skybox, &options.color_map, &options.defines)?),
+    };
+    let target = options.record_target().or_else(|| is_y4m_path(&options.output).then(|| RecordTarget::File(options.output.clone())));
+    let mut stream = match &target {
+        Some(target) => Some(Y4mWriter::create(target, width, height, timeline.fps)?),
+        None => None,
+    };
+
+    let frames = timeline.frame_count();
+    let start = Instant::now();
+    for frame in 0..frames {
+        let mut scene = timeline.sample(frame as f64 / timeline.fps);
+        scene.apply_options(options);
+        if let Some(stream) = &mut stream {
+            let pixels = match (&mut app, &cpu) {
+                (Some(app), _) => {
+                    app.set_scene(&scene);
+                    app.render_rgba(&app.frame_params(width, height, scene.time))?
+                }
+                (None, Some(cpu)) => cpu.render(&FrameParams::from_scene(&scene, width, height)).into_raw(),
+                (None, None) => unreachable!("one of the backends is always set up"),
+            };
+            stream.write_frame(&pixels)?;
+        } else {
+            let path = frame_path(&options.output, frame);
+            if let Some(app) = &mut app {
+                app.set_scene(&scene);
+                app.render_to_file(width, height, scene.time, options.tile_size, &path)?;
+            } else if let Some(cpu) = &cpu {
+                cpu.render_to_file(&scene, width, height, &path, hash.clone())?;
+            }
+        }
+        report_progress("frame", frame as usize + 1, frames as usize, start);
+    }
+    if let Some(stream) = stream {
+        stream.finish()?;
+    }
+    Ok(())
+}
+
+fn is_y4m_path(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| ext.eq_ignore_ascii_case("y4m"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Timeline {
+        Timeline::parse(text, Path::new("")).unwrap()
+    }
+
+    #[test]
+    fn angles_take_the_short_way_round() {
+        let timeline = parse(
+            "[[keyframe]]\ntime = 0.0\nease = \"linear\"\ncamera.azimuth = 350.0\ncamera.roll = -170.0\n\
+             [[keyframe]]\ntime = 1.0\ncamera.azimuth = 10.0\ncamera.roll = 170.0\n\
+             [[keyframe]]\ntime = 2.0\ncamera.azimuth = 100.0\n",
+        );
+        let [first, second, third] = [0, 1, 2].map(|i| &timeline.keyframes[i].scene);
+        assert_eq!((first.azimuth, second.azimuth, third.azimuth), (350.0, 370.0, 460.0));
+        assert_eq!((first.roll, second.roll, third.roll), (-170.0, -190.0, -190.0));
+        let halfway = timeline.sample(0.5);
+        assert!((halfway.azimuth - 360.0).abs() < 1e-3, "{}", halfway.azimuth);
+    }
+
+    #[test]
+    fn disk_time_runs_on_linearly_whatever_the_ease() {
+        let timeline = parse(
+            "[[keyframe]]\ntime = 0.0\nease = \"hold\"\ncamera.fov = 40.0\n\
+             [[keyframe]]\ntime = 2.0\nease = \"ease_in_out\"\ncamera.fov = 60.0\n\
+             [[keyframe]]\ntime = 4.0\n",
+        );
+        for t in [0.0, 0.5, 1.0, 1.5, 2.5, 3.0, 3.5] {
+            assert!((timeline.sample(t).time as f64 - t).abs() < 1e-5, "{}", t);
+        }
+        assert_eq!(timeline.sample(1.5).fov, 40.0);
+    }
+
+    #[test]
+    fn half_turns_keep_their_direction() {
+        assert_eq!(nearest_turn(180.0, 0.0), 180.0);
+        assert_eq!(nearest_turn(-180.0, 0.0), -180.0);
+        assert_eq!(nearest_turn(720.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn every_ease_starts_and_ends_on_the_keyframes() {
+        let (values, times) = ([-4.0, 1.0, 3.0, 10.0], [0.0, 1.0, 3.0, 4.0]);
+        for ease in [Ease::CatmullRom, Ease::Linear, Ease::InOut] {
+            assert!((ease.interpolate(values, times, 0.0) - 1.0).abs() < 1e-12, "{:?}", ease);
+            assert!((ease.interpolate(values, times, 1.0) - 3.0).abs() < 1e-12, "{:?}", ease);
+        }
+        assert_eq!(Ease::Linear.interpolate(values, times, 0.25), 1.5);
+        assert_eq!(Ease::InOut.interpolate(values, times, 0.5), 2.0);
+        assert_eq!(Ease::Hold.interpolate(values, times, 0.99), 1.0);
+    }
+
+    #[test]
+    fn keys_carry_over_and_time_runs_on() {
+        let timeline = parse(
+            "[timeline]\nfps = 24.0\n\
+             [[keyframe]]\ntime = 0.0\ncamera.fov = 50.0\nrender.time = 5.0\n\
+             [[keyframe]]\ntime = 2.0\nease = \"hold\"\ndisk.enabled = false\n",
+        );
+        let last = &timeline.keyframes[1];
+        assert_eq!((last.scene.fov, last.scene.time, last.scene.render_disk), (50.0, 7.0, false));
+        assert_eq!(timeline.sample(-1.0).fov, 50.0);
+        assert!(timeline.sample(1.0).render_disk);
+        assert!(!timeline.sample(5.0).render_disk);
+    }
+
+    #[test]
+    fn frames_cover_the_last_keyframe() {
+        let timeline = parse("[timeline]\nfps = 30.0\n[[keyframe]]\ntime = 0.0\n[[keyframe]]\ntime = 0.1\n");
+        assert_eq!(timeline.frame_count(), 4);
+        let timeline = parse("[[keyframe]]\ntime = 0.0\n");
+        assert_eq!(timeline.frame_count(), 1);
+    }
+
+    #[test]
+    fn rejects_bad_timelines() {
+        let parse = |text: &str| Timeline::parse(text, Path::new(""));
+        assert!(parse("[timeline]\nfps = 30.0\n").is_err());
+        assert!(parse("[[keyframe]]\ncamera.fov = 50.0\n").is_err());
+        assert!(parse("[[keyframe]]\ntime = 1.0\n[[keyframe]]\ntime = 1.0\n").is_err());
+        assert!(parse("[[keyframe]]\ntime = 0.0\nease = \"bounce\"\n").is_err());
+        assert!(parse("[[keyframe]]\ntime = 0.0\ncamera.fvo = 50.0\n").is_err());
+        assert!(parse("[timeline]\nfps = 0.0\n[[keyframe]]\ntime = 0.0\n").is_err());
+    }
+
+    #[test]
+    fn numbers_frame_paths() {
+        assert_eq!(frame_path(Path::new("frames/flyby_#####.png"), 42), Path::new("frames/flyby_00042.png"));
+        assert_eq!(frame_path(Path::new("out/f##"), 7), Path::new("out/f07"));
+        assert_eq!(frame_path(Path::new("out/#.png"), 123), Path::new("out/123.png"));
+        assert_eq!(frame_path(Path::new("render.png"), 3), Path::new("render_00003.png"));
+        assert_eq!(frame_path(Path::new("render"), 3), Path::new("render_00003"));
+    }
+}